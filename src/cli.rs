@@ -1,3 +1,6 @@
+use crate::write::{
+    CsvCompression, IpcOutputCompression, OutputFormat, SqliteTables, TextEncoding, XportVersion,
+};
 use clap::Parser;
 use std::path::PathBuf;
 
@@ -39,6 +42,75 @@ pub struct Cli {
     /// Input path to read from
     #[arg(short, long, env = "CDEF_INPUT_PATH")]
     pub input: Option<PathBuf>,
+
+    /// Seed for reproducible output; a random seed is used if not provided
+    #[arg(long, env = "CDEF_SEED")]
+    pub seed: Option<u64>,
+
+    /// Allow CPR numbers without a valid modulus-11 check for people born from October 2007
+    #[arg(long, env = "CDEF_ALLOW_NON_MODULUS11")]
+    pub allow_non_modulus11: bool,
+
+    /// Carry the same population forward from year to year instead of drawing a new one per year
+    #[arg(long, env = "CDEF_PANEL")]
+    pub panel: bool,
+
+    /// Write schema columns that have no generator as nulls instead of stopping
+    #[arg(long, env = "CDEF_FILL_UNSUPPORTED")]
+    pub fill_unsupported: bool,
+
+    /// Store the labels of coded columns, such as CIVST, and the descriptions of the columns
+    /// in the Parquet file metadata. Stata output always has them as value and variable labels
+    #[arg(long, env = "CDEF_LABELS")]
+    pub labels: bool,
+
+    /// Directory with schema files that replace or add to the built-in schemas
+    #[arg(long, env = "CDEF_SCHEMA_DIR")]
+    pub schema_dir: Option<PathBuf>,
+
+    /// Directory with mapping files that replace or add to the built-in mappings
+    #[arg(long, env = "CDEF_MAPPING_DIR")]
+    pub mapping_dir: Option<PathBuf>,
+
+    /// File format of the output, both when generating and when converting input
+    #[arg(long, env = "CDEF_FORMAT", value_enum, default_value_t = OutputFormat::Parquet)]
+    pub format: OutputFormat,
+
+    /// Field delimiter of CSV output; use "tab" or "\t" for tab-separated files
+    #[arg(long, env = "CDEF_CSV_DELIMITER", default_value = ";")]
+    pub csv_delimiter: String,
+
+    /// Write decimals in CSV output with a decimal comma, as in the Danish locale
+    #[arg(long, env = "CDEF_CSV_DECIMAL_COMMA")]
+    pub csv_decimal_comma: bool,
+
+    /// Format of dates in CSV output, e.g. "%d-%m-%Y"
+    #[arg(long, env = "CDEF_CSV_DATE_FORMAT", default_value = "%Y-%m-%d")]
+    pub csv_date_format: String,
+
+    /// Character encoding of text in CSV and SAS transport output
+    #[arg(long, env = "CDEF_ENCODING", value_enum, default_value_t = TextEncoding::Utf8)]
+    pub encoding: TextEncoding,
+
+    /// Compression of CSV output
+    #[arg(long, env = "CDEF_CSV_COMPRESSION", value_enum, default_value_t = CsvCompression::None)]
+    pub csv_compression: CsvCompression,
+
+    /// Version of SAS transport output; version 5 limits names to 8 characters
+    #[arg(long, env = "CDEF_XPORT_VERSION", value_enum, default_value_t = XportVersion::V5)]
+    pub xport_version: XportVersion,
+
+    /// Compression of Arrow IPC output
+    #[arg(long, env = "CDEF_IPC_COMPRESSION", value_enum, default_value_t = IpcOutputCompression::None)]
+    pub ipc_compression: IpcOutputCompression,
+
+    /// Replace an existing SQLite database instead of stopping
+    #[arg(long, env = "CDEF_OVERWRITE")]
+    pub overwrite: bool,
+
+    /// Tables of SQLite output: one per register with a YEAR column, or one per register and year
+    #[arg(long, env = "CDEF_SQLITE_TABLES", value_enum, default_value_t = SqliteTables::Register)]
+    pub sqlite_tables: SqliteTables,
 }
 
 impl Cli {
@@ -55,4 +127,17 @@ impl Cli {
         }
         Ok((self.start_year, end_year))
     }
+
+    pub fn get_csv_delimiter(&self) -> Result<u8, crate::error::DataGeneratorError> {
+        match self.csv_delimiter.as_str() {
+            "tab" | "\\t" => Ok(b'\t'),
+            delimiter => match delimiter.as_bytes() {
+                [byte] if byte.is_ascii() && *byte != b'"' && *byte != b'\n' => Ok(*byte),
+                _ => Err(crate::error::DataGeneratorError::Other(format!(
+                    "The CSV delimiter must be a single ASCII character, not \"{}\"",
+                    delimiter
+                ))),
+            },
+        }
+    }
 }
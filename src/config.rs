@@ -1,3 +1,4 @@
+use crate::write::{CsvOptions, IpcOutputCompression, OutputFormat, SqliteTables, XportOptions};
 use std::path::PathBuf;
 
 pub struct Config {
@@ -7,11 +8,30 @@ pub struct Config {
     pub threads: usize,
     pub output: Option<PathBuf>,
     pub input: Option<PathBuf>,
+    pub allow_non_modulus11: bool,
+    pub seed: u64,
+    pub panel: bool,
+    pub fill_unsupported: bool,
+    pub labels: bool,
+    pub schema_dir: Option<PathBuf>,
+    pub mapping_dir: Option<PathBuf>,
+    pub format: OutputFormat,
+    pub csv: CsvOptions,
+    pub xport: XportOptions,
+    pub ipc_compression: IpcOutputCompression,
+    pub sqlite_tables: SqliteTables,
+    pub overwrite: bool,
 }
 
 impl Config {
     pub fn new(cli: &crate::cli::Cli) -> Result<Self, crate::error::DataGeneratorError> {
         let (start_year, end_year) = cli.get_years()?;
+        if cli.labels && !matches!(cli.format, OutputFormat::Parquet | OutputFormat::Stata) {
+            return Err(crate::error::DataGeneratorError::Other(
+                "--labels is only supported for Parquet output; Stata output always has value labels"
+                    .to_string(),
+            ));
+        }
         Ok(Self {
             registers: cli.registers.clone(),
             years: (start_year, end_year),
@@ -19,6 +39,40 @@ impl Config {
             threads: cli.threads,
             output: cli.output.clone(),
             input: cli.input.clone(),
+            allow_non_modulus11: cli.allow_non_modulus11,
+            seed: cli.seed.unwrap_or_else(rand::random),
+            panel: cli.panel,
+            fill_unsupported: cli.fill_unsupported,
+            labels: cli.labels,
+            schema_dir: cli.schema_dir.clone(),
+            mapping_dir: cli.mapping_dir.clone(),
+            format: cli.format,
+            csv: CsvOptions {
+                delimiter: cli.get_csv_delimiter()?,
+                decimal_comma: cli.csv_decimal_comma,
+                date_format: cli.csv_date_format.clone(),
+                encoding: cli.encoding,
+                compression: cli.csv_compression,
+            },
+            xport: XportOptions {
+                version: cli.xport_version,
+                encoding: cli.encoding,
+            },
+            ipc_compression: cli.ipc_compression,
+            sqlite_tables: cli.sqlite_tables,
+            overwrite: cli.overwrite,
         })
     }
+
+    /// The extension of output files in the chosen format.
+    pub fn file_extension(&self) -> String {
+        match self.format {
+            OutputFormat::Parquet => "parquet".to_string(),
+            OutputFormat::Csv => self.csv.extension(),
+            OutputFormat::Xport => "xpt".to_string(),
+            OutputFormat::Stata => "dta".to_string(),
+            OutputFormat::Ipc => "arrow".to_string(),
+            OutputFormat::Sqlite => "sqlite".to_string(),
+        }
+    }
 }
@@ -1,5 +1,31 @@
 use thiserror::Error;
 
+/// A schema column that neither the schema nor the register's code can produce.
+#[derive(Debug)]
+pub struct UnsupportedColumn {
+    pub register: String,
+    pub column: String,
+    pub year: i32,
+}
+
+/// Lists the columns with the years they're missing in, e.g.
+/// `bef.VERSION (2019, 2020)`.
+pub fn describe_unsupported(columns: &[UnsupportedColumn]) -> String {
+    let mut described: Vec<(String, Vec<String>)> = Vec::new();
+    for column in columns {
+        let name = format!("{}.{}", column.register, column.column);
+        match described.last_mut() {
+            Some((last, years)) if *last == name => years.push(column.year.to_string()),
+            _ => described.push((name, vec![column.year.to_string()])),
+        }
+    }
+    described
+        .into_iter()
+        .map(|(name, years)| format!("{} ({})", name, years.join(", ")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 #[derive(Error, Debug)]
 pub enum DataGeneratorError {
     #[error("IO error: {0}")]
@@ -8,12 +34,40 @@ pub enum DataGeneratorError {
     Json(#[from] serde_json::Error),
     #[error("Polars error: {0}")]
     Polars(#[from] polars::error::PolarsError),
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
     #[error("CLI argument error: {0}")]
     Cli(#[from] clap::Error),
     #[error("Other error: {0}")]
     Other(String),
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+    #[error("Invalid PNR: {0}")]
+    InvalidPnr(String),
+    #[error("No free PNRs left for birth date {birth_date} and sex {sex}")]
+    PnrExhausted {
+        birth_date: chrono::NaiveDate,
+        sex: char,
+    },
+    #[error("Column {column} of register '{register}' for {year} can't be produced as {dtype}: {reason}")]
+    ColumnType {
+        register: String,
+        column: String,
+        year: i32,
+        dtype: String,
+        reason: String,
+    },
+    #[error(
+        "No generator for the schema columns {}; add a generator to the schema or pass --fill-unsupported to write them as nulls",
+        describe_unsupported(.0)
+    )]
+    UnsupportedColumns(Vec<UnsupportedColumn>),
+    #[error("Mapping '{file}' can't be loaded: {reason}")]
+    InvalidMapping { file: String, reason: String },
+    #[error("Unknown register '{name}', available registers are: {available}")]
+    UnknownRegister { name: String, available: String },
+    #[error("Register '{name}' links to the contacts of '{dependency}'; add it to --registers")]
+    MissingDependency { name: String, dependency: String },
 }
 
 impl From<Box<dyn std::error::Error>> for DataGeneratorError {
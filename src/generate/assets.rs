@@ -0,0 +1,115 @@
+use crate::error::DataGeneratorError;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// The schemas shipped with the generator, compiled into the binary.
+static EMBEDDED_SCHEMAS: &[(&str, &str)] = &[
+    ("akm.json", include_str!("../../schemas/akm.json")),
+    ("bef.json", include_str!("../../schemas/bef.json")),
+    ("dod.json", include_str!("../../schemas/dod.json")),
+    ("idan.json", include_str!("../../schemas/idan.json")),
+    ("ind.json", include_str!("../../schemas/ind.json")),
+    (
+        "lpr3_diagnoser.json",
+        include_str!("../../schemas/lpr3_diagnoser.json"),
+    ),
+    (
+        "lpr3_kontakter.json",
+        include_str!("../../schemas/lpr3_kontakter.json"),
+    ),
+    ("lpr_adm.json", include_str!("../../schemas/lpr_adm.json")),
+    ("lpr_bes.json", include_str!("../../schemas/lpr_bes.json")),
+    ("lpr_diag.json", include_str!("../../schemas/lpr_diag.json")),
+    ("uddf.json", include_str!("../../schemas/uddf.json")),
+    ("vnds.json", include_str!("../../schemas/vnds.json")),
+];
+
+/// The mappings shipped with the generator, compiled into the binary.
+static EMBEDDED_MAPPINGS: &[(&str, &str)] = &[
+    (
+        "beskst13.json",
+        include_str!("../../mappings/beskst13.json"),
+    ),
+    ("civst.json", include_str!("../../mappings/civst.json")),
+    (
+        "familie_type.json",
+        include_str!("../../mappings/familie_type.json"),
+    ),
+    ("fm_mark.json", include_str!("../../mappings/fm_mark.json")),
+    ("hustype.json", include_str!("../../mappings/hustype.json")),
+    ("icd10.json", include_str!("../../mappings/icd10.json")),
+    ("jobkat.json", include_str!("../../mappings/jobkat.json")),
+    ("kom.json", include_str!("../../mappings/kom.json")),
+    ("plads.json", include_str!("../../mappings/plads.json")),
+    (
+        "pre_socio.json",
+        include_str!("../../mappings/pre_socio.json"),
+    ),
+    ("reg.json", include_str!("../../mappings/reg.json")),
+    ("scd.json", include_str!("../../mappings/scd.json")),
+    ("socio.json", include_str!("../../mappings/socio.json")),
+    ("socio02.json", include_str!("../../mappings/socio02.json")),
+    ("socio13.json", include_str!("../../mappings/socio13.json")),
+    ("statsb.json", include_str!("../../mappings/statsb.json")),
+    ("tilknyt.json", include_str!("../../mappings/tilknyt.json")),
+];
+
+/// Directories whose files take precedence over the embedded ones.
+struct AssetDirs {
+    schema_dir: Option<PathBuf>,
+    mapping_dir: Option<PathBuf>,
+}
+
+static ASSET_DIRS: RwLock<AssetDirs> = RwLock::new(AssetDirs {
+    schema_dir: None,
+    mapping_dir: None,
+});
+
+/// Sets the directories to read schemas and mappings from before falling
+/// back to the embedded files. A directory can replace some of the shipped
+/// files and add new ones.
+pub fn set_asset_dirs(schema_dir: Option<PathBuf>, mapping_dir: Option<PathBuf>) {
+    let mut dirs = ASSET_DIRS.write().unwrap();
+    dirs.schema_dir = schema_dir;
+    dirs.mapping_dir = mapping_dir;
+}
+
+/// Reads a schema file such as `bef.json`.
+pub fn read_schema(file_name: &str) -> Result<String, DataGeneratorError> {
+    let dir = ASSET_DIRS.read().unwrap().schema_dir.clone();
+    read_asset(dir.as_deref(), EMBEDDED_SCHEMAS, file_name)
+        .map_err(|e| {
+            DataGeneratorError::Other(format!("Failed to read schema file '{}': {}", file_name, e))
+        })?
+        .ok_or_else(|| DataGeneratorError::Other(format!("Schema file '{}' not found", file_name)))
+}
+
+/// Reads a mapping file such as `reg.json`.
+pub fn read_mapping(file_name: &str) -> Result<String, DataGeneratorError> {
+    let dir = ASSET_DIRS.read().unwrap().mapping_dir.clone();
+    let invalid_mapping = |reason: String| DataGeneratorError::InvalidMapping {
+        file: file_name.to_string(),
+        reason,
+    };
+    read_asset(dir.as_deref(), EMBEDDED_MAPPINGS, file_name)
+        .map_err(|e| invalid_mapping(e.to_string()))?
+        .ok_or_else(|| invalid_mapping("no such mapping".to_string()))
+}
+
+fn read_asset(
+    dir: Option<&Path>,
+    embedded: &[(&str, &str)],
+    file_name: &str,
+) -> std::io::Result<Option<String>> {
+    if let Some(path) = dir
+        .map(|dir| dir.join(file_name))
+        .filter(|path| path.exists())
+    {
+        return Ok(Some(fs::read_to_string(path)?));
+    }
+    Ok(embedded
+        .iter()
+        .find(|(name, _)| *name == file_name)
+        .map(|(_, content)| content.to_string()))
+}
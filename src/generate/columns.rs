@@ -0,0 +1,211 @@
+use crate::error::{DataGeneratorError, UnsupportedColumn};
+use crate::generate::context::RegisterContext;
+use crate::generate::mappings::CodeList;
+use crate::generate::pnr::decode_pnr;
+use crate::generate::rng::SeedStream;
+use crate::generate::schema::{
+    cast_to_schema, parse_dtype, ColumnGenerator, NullRate, PersonAttribute, Schema,
+};
+use chrono::{Datelike, NaiveDate};
+use polars::prelude::*;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use serde_json::Value;
+
+/// Builds one year of a register with the columns and types of its schema.
+///
+/// Columns declaring a generator are produced from the schema, all others by
+/// `series`, the register's own code. Columns neither can produce are nulls
+/// if the context allows it, and an error otherwise.
+pub fn build_frame(
+    register: &str,
+    schema: &Schema,
+    ctx: &RegisterContext,
+    series: impl Fn(&str) -> Option<Series>,
+) -> Result<DataFrame, DataGeneratorError> {
+    let mut columns = Vec::with_capacity(schema.columns.len());
+    for column in schema.columns_for(ctx.year) {
+        let col_name = column.name.as_str();
+        let column_type_error = |reason: String| DataGeneratorError::ColumnType {
+            register: register.to_string(),
+            column: col_name.to_string(),
+            year: ctx.year,
+            dtype: column.type_name.clone(),
+            reason,
+        };
+        let dtype = parse_dtype(&column.type_name)
+            .ok_or_else(|| column_type_error("unknown type".to_string()))?;
+
+        let generated = match &column.generator {
+            Some(generator) => {
+                generate_column(col_name, generator, &dtype, ctx).map_err(column_type_error)?
+            }
+            None => match series(col_name) {
+                Some(series) => series,
+                None if ctx.fill_unsupported => {
+                    Series::full_null(col_name.into(), ctx.no_rows(), &dtype)
+                }
+                None => {
+                    return Err(DataGeneratorError::UnsupportedColumns(vec![
+                        UnsupportedColumn {
+                            register: register.to_string(),
+                            column: col_name.to_string(),
+                            year: ctx.year,
+                        },
+                    ]))
+                }
+            },
+        };
+        let generated = match &column.null_rate {
+            Some(null_rate) => {
+                let seed = ctx.seed.derive(col_name).derive("nulls");
+                with_nulls(&generated, null_rate, ctx, seed).map_err(column_type_error)?
+            }
+            None => generated,
+        };
+        columns.push(cast_to_schema(&generated, &dtype).map_err(column_type_error)?);
+    }
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Generates a column declared with a generator in the schema.
+///
+/// Codes from mappings are those in use in the year, as numbers when the
+/// column is numeric and strings otherwise; categories keep the JSON type of
+/// their values. The result is cast to the declared type afterwards like any
+/// other column.
+fn generate_column(
+    col_name: &str,
+    generator: &ColumnGenerator,
+    dtype: &DataType,
+    ctx: &RegisterContext,
+) -> Result<Series, String> {
+    let name = PlSmallStr::from(col_name);
+    let no_rows = ctx.no_rows();
+    let seed = ctx.seed.derive(col_name);
+
+    let series = match generator {
+        ColumnGenerator::Mapping { file } => {
+            let code_list = CodeList::<String>::load(file).map_err(|e| e.to_string())?;
+            let codes_in_year = code_list.in_year(ctx.year).map_err(|e| e.to_string())?;
+            if dtype.is_numeric() {
+                let codes = code_list
+                    .codes()
+                    .iter()
+                    .map(|code| {
+                        code.parse::<i64>()
+                            .map_err(|_| format!("code '{}' in {} isn't a number", code, file))
+                    })
+                    .collect::<Result<Vec<i64>, String>>()?;
+                let data: Vec<i64> =
+                    seed.par_rows(no_rows, |rng, _| codes[codes_in_year.sample_index(rng)]);
+                Series::new(name, data)
+            } else {
+                let data: Vec<&str> =
+                    seed.par_rows(no_rows, |rng, _| codes_in_year.sample(rng).as_str());
+                Series::new(name, data)
+            }
+        }
+        ColumnGenerator::IntRange { min, max } => {
+            if min > max {
+                return Err(format!("the range {}..={} is empty", min, max));
+            }
+            let data: Vec<i64> = seed.par_rows(no_rows, |rng, _| rng.gen_range(*min..=*max));
+            Series::new(name, data)
+        }
+        ColumnGenerator::Categories { values, weights } => {
+            if values.is_empty() {
+                return Err("no categories are listed".to_string());
+            }
+            let indices: Vec<usize> = match weights {
+                Some(weights) => {
+                    if weights.len() != values.len() {
+                        return Err(format!(
+                            "{} weights are given for {} categories",
+                            weights.len(),
+                            values.len()
+                        ));
+                    }
+                    let distribution = WeightedIndex::new(weights)
+                        .map_err(|e| format!("invalid category weights: {}", e))?;
+                    seed.par_rows(no_rows, |rng, _| distribution.sample(rng))
+                }
+                None => seed.par_rows(no_rows, |rng, _| rng.gen_range(0..values.len())),
+            };
+            categories_series(name, values, &indices)?
+        }
+        ColumnGenerator::DateInYear => {
+            let data: Vec<NaiveDate> = seed.par_rows(no_rows, |rng, i| ctx.date_for_row(i, rng));
+            Series::new(name, data)
+        }
+    };
+    Ok(series)
+}
+
+/// Picks `values[i]` for every index, keeping the values' JSON type.
+fn categories_series(
+    name: PlSmallStr,
+    values: &[Value],
+    indices: &[usize],
+) -> Result<Series, String> {
+    fn all<'a, T>(values: &'a [Value], f: impl Fn(&'a Value) -> Option<T>) -> Option<Vec<T>> {
+        values.iter().map(f).collect()
+    }
+
+    if let Some(values) = all(values, Value::as_str) {
+        Ok(Series::new(
+            name,
+            indices.iter().map(|&i| values[i]).collect::<Vec<_>>(),
+        ))
+    } else if let Some(values) = all(values, Value::as_i64) {
+        Ok(Series::new(
+            name,
+            indices.iter().map(|&i| values[i]).collect::<Vec<_>>(),
+        ))
+    } else if let Some(values) = all(values, Value::as_f64) {
+        Ok(Series::new(
+            name,
+            indices.iter().map(|&i| values[i]).collect::<Vec<_>>(),
+        ))
+    } else if let Some(values) = all(values, Value::as_bool) {
+        Ok(Series::new(
+            name,
+            indices.iter().map(|&i| values[i]).collect::<Vec<_>>(),
+        ))
+    } else {
+        Err("categories must all be strings, numbers or booleans".to_string())
+    }
+}
+
+/// Blanks out a random share of the rows of `series`, which may depend on
+/// the person behind each row.
+fn with_nulls(
+    series: &Series,
+    null_rate: &NullRate,
+    ctx: &RegisterContext,
+    seed: SeedStream,
+) -> Result<Series, String> {
+    if let Some(rate) = null_rate
+        .rates()
+        .into_iter()
+        .find(|rate| !(0.0..=1.0).contains(rate))
+    {
+        return Err(format!("the null rate {} isn't between 0 and 1", rate));
+    }
+    let attribute = |row: usize| match null_rate {
+        NullRate::Fixed(_) => None,
+        NullRate::Conditional { by, .. } => {
+            let birth_year = decode_pnr(&ctx.pnrs[row]).ok()?.birth_date.year();
+            Some(match by {
+                PersonAttribute::Age => ctx.year - birth_year,
+                PersonAttribute::BirthYear => birth_year,
+            })
+        }
+    };
+    let rows: Vec<Option<IdxSize>> = seed.par_rows(series.len(), |rng, i| {
+        (!rng.gen_bool(null_rate.rate(attribute(i)))).then_some(i as IdxSize)
+    });
+    series
+        .take(&IdxCa::new(PlSmallStr::EMPTY, rows))
+        .map_err(|e| e.to_string())
+}
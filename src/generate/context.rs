@@ -0,0 +1,41 @@
+use crate::generate::population::Population;
+use crate::generate::recnum::RecnumPool;
+use crate::generate::rng::SeedStream;
+use crate::generate::utils::generate_date_for_year;
+use chrono::{Datelike, Duration, NaiveDate};
+use rand::Rng;
+use std::sync::Mutex;
+
+/// Everything a register needs to generate the columns of one year.
+pub struct RegisterContext<'a> {
+    pub year: i32,
+    pub population: &'a Population,
+    /// The person behind each row of the register.
+    pub pnrs: Vec<String>,
+    /// The key of the contact behind each row, for registers whose rows
+    /// belong to the contacts of the registers they depend on.
+    pub contacts: Vec<String>,
+    pub seed: SeedStream,
+    pub recnums: &'a Mutex<RecnumPool>,
+    /// Write columns without a generator as nulls instead of failing.
+    pub fill_unsupported: bool,
+}
+
+impl RegisterContext<'_> {
+    pub fn no_rows(&self) -> usize {
+        self.pnrs.len()
+    }
+
+    /// A random date in the year, no later than the death of the person
+    /// behind `row`.
+    pub fn date_for_row(&self, row: usize, rng: &mut impl Rng) -> NaiveDate {
+        match self.population.death_date(&self.pnrs[row]) {
+            Some(death_date) => {
+                let first_day = NaiveDate::from_ymd_opt(self.year, 1, 1).unwrap();
+                let days = rng.gen_range(0..=death_date.ordinal0());
+                first_day + Duration::days(days as i64)
+            }
+            None => generate_date_for_year(self.year, rng),
+        }
+    }
+}
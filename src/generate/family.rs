@@ -0,0 +1,332 @@
+use crate::generate::population::{Person, Population};
+use chrono::Datelike;
+use std::collections::{BTreeMap, HashMap};
+
+/// Children count as living at home with their family until they turn 18.
+const ADULT_AGE: i32 = 18;
+
+/// Where a person stands in their family and household at the end of the year.
+pub struct FamilyPosition {
+    /// PNR of the family's reference person: the woman in a couple, or the
+    /// adult or child living alone.
+    pub family_id: String,
+    pub family_type: i8,
+    /// Spouse or partner living in the same family.
+    pub partner_pnr: Option<String>,
+    pub children_in_family: i8,
+    pub persons_in_family: i8,
+    pub children_in_household: i8,
+    pub persons_in_household: i8,
+    pub hustype: i8,
+    pub fm_mark: i8,
+    pub plads: i8,
+}
+
+/// A family within a household: one or two adults and the children living
+/// with them.
+struct Family {
+    adults: Vec<usize>,
+    children: Vec<usize>,
+}
+
+impl Family {
+    fn len(&self) -> usize {
+        self.adults.len() + self.children.len()
+    }
+
+    fn family_type(&self, persons: &[Person], year: i32) -> i8 {
+        match self.adults.as_slice() {
+            [a, b] => match (persons[*a].civst, persons[*b].civst) {
+                ("G", "G") => 1,
+                ("P", "P") => 2,
+                _ if self.has_common_children(persons) => 3,
+                _ => 4,
+            },
+            [a] if year - persons[*a].birth_date.year() < ADULT_AGE => 7,
+            [a] if persons[*a].gender == 'M' => 5,
+            _ => 6,
+        }
+    }
+
+    fn has_common_children(&self, persons: &[Person]) -> bool {
+        let is_adult = |pnr: &Option<String>| {
+            self.adults
+                .iter()
+                .any(|&i| pnr.as_deref() == Some(persons[i].pnr.as_str()))
+        };
+        self.children
+            .iter()
+            .any(|&i| is_adult(&persons[i].mother_pnr) && is_adult(&persons[i].father_pnr))
+    }
+
+    fn reference_person(&self, persons: &[Person]) -> usize {
+        *self
+            .adults
+            .iter()
+            .find(|&&i| persons[i].gender == 'K')
+            .unwrap_or(&self.adults[0])
+    }
+}
+
+/// The household type follows from its families, see `mappings/hustype.json`.
+fn hustype(families: &[Family], family_type: i8) -> i8 {
+    if families.len() > 1 {
+        return 6;
+    }
+    match family_type {
+        1 | 2 => 3,
+        3 | 4 => 4,
+        5 => 1,
+        6 => 2,
+        _ => 5,
+    }
+}
+
+/// Whom the person lives with among their parents, see `mappings/fm_mark.json`.
+fn fm_mark(
+    person: &Person,
+    is_child: bool,
+    family: &Family,
+    household: &HashMap<&str, usize>,
+) -> i8 {
+    let lives_with = |parent: &Option<String>| {
+        parent
+            .as_deref()
+            .is_some_and(|pnr| household.contains_key(pnr))
+    };
+    let parent_in_couple = family.adults.len() == 2;
+    match (
+        lives_with(&person.mother_pnr),
+        lives_with(&person.father_pnr),
+    ) {
+        (true, true) => 1,
+        (true, false) if !is_child || parent_in_couple => 2,
+        (true, false) => 3,
+        (false, true) if !is_child || parent_in_couple => 4,
+        (false, true) => 5,
+        (false, false) => 6,
+    }
+}
+
+/// Splits a household into families. Partners living together form a couple,
+/// children under 18 join the family of their mother, or otherwise their
+/// father, and everyone else forms a family of their own.
+fn split_families(members: &[usize], persons: &[Person], year: i32) -> Vec<Family> {
+    let index: HashMap<&str, usize> = members
+        .iter()
+        .map(|&i| (persons[i].pnr.as_str(), i))
+        .collect();
+    let is_minor = |i: usize| year - persons[i].birth_date.year() < ADULT_AGE;
+    let parent_at_home = |i: usize| {
+        [&persons[i].mother_pnr, &persons[i].father_pnr]
+            .into_iter()
+            .flatten()
+            .filter_map(|pnr| index.get(pnr.as_str()).copied())
+            .find(|&parent| !is_minor(parent))
+    };
+
+    let mut families: Vec<Family> = Vec::new();
+    let mut family_of: HashMap<usize, usize> = HashMap::new();
+    for &i in members {
+        if family_of.contains_key(&i) || (is_minor(i) && parent_at_home(i).is_some()) {
+            continue;
+        }
+        let mut adults = vec![i];
+        if let Some(&partner) = [&persons[i].spouse_pnr, &persons[i].cohabitant_pnr]
+            .into_iter()
+            .flatten()
+            .find_map(|pnr| index.get(pnr.as_str()))
+        {
+            adults.push(partner);
+        }
+        for &adult in &adults {
+            family_of.insert(adult, families.len());
+        }
+        families.push(Family {
+            adults,
+            children: Vec::new(),
+        });
+    }
+    for &i in members {
+        if let Some(parent) = parent_at_home(i).filter(|_| is_minor(i)) {
+            families[family_of[&parent]].children.push(i);
+        }
+    }
+
+    families
+}
+
+/// Places every person in the population in a family and household, in the
+/// same order as [`Population::persons`].
+pub fn family_positions(population: &Population) -> Vec<FamilyPosition> {
+    positions_of(population.persons(), population.year())
+}
+
+fn positions_of(persons: &[Person], year: i32) -> Vec<FamilyPosition> {
+    let mut households: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (i, person) in persons.iter().enumerate() {
+        households.entry(person.household_id).or_default().push(i);
+    }
+
+    let mut positions: Vec<Option<FamilyPosition>> = persons.iter().map(|_| None).collect();
+    for members in households.values() {
+        let families = split_families(members, persons, year);
+        let household: HashMap<&str, usize> = members
+            .iter()
+            .map(|&i| (persons[i].pnr.as_str(), i))
+            .collect();
+        let children_in_household: usize = families.iter().map(|f| f.children.len()).sum();
+
+        for family in &families {
+            let reference = family.reference_person(persons);
+            let family_type = family.family_type(persons, year);
+            let roles = family
+                .adults
+                .iter()
+                .map(|&i| (i, false))
+                .chain(family.children.iter().map(|&i| (i, true)));
+
+            for (i, is_child) in roles {
+                let partner_pnr = family
+                    .adults
+                    .iter()
+                    .find(|&&adult| !is_child && adult != i)
+                    .map(|&adult| persons[adult].pnr.clone());
+                let plads = match (is_child, i == reference) {
+                    (true, _) => 3,
+                    (false, true) => 1,
+                    (false, false) => 2,
+                };
+                positions[i] = Some(FamilyPosition {
+                    family_id: persons[reference].pnr.clone(),
+                    family_type,
+                    partner_pnr,
+                    children_in_family: family.children.len() as i8,
+                    persons_in_family: family.len() as i8,
+                    children_in_household: children_in_household as i8,
+                    persons_in_household: members.len() as i8,
+                    hustype: hustype(&families, family_type),
+                    fm_mark: fm_mark(&persons[i], is_child, family, &household),
+                    plads,
+                });
+            }
+        }
+    }
+
+    positions.into_iter().map(Option::unwrap).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::migration::{Origin, DENMARK};
+    use chrono::NaiveDate;
+
+    fn person(pnr: &str, birth_year: i32, gender: char, household_id: u64) -> Person {
+        Person {
+            pnr: pnr.to_string(),
+            birth_date: NaiveDate::from_ymd_opt(birth_year, 6, 1).unwrap(),
+            gender,
+            mother_pnr: None,
+            father_pnr: None,
+            civst: "U",
+            spouse_pnr: None,
+            cohabitant_pnr: None,
+            household_id,
+            moved_in: NaiveDate::from_ymd_opt(2015, 1, 1).unwrap(),
+            kom: 101,
+            origin: Origin::Danish,
+            origin_country: DENMARK,
+            citizenship: DENMARK,
+        }
+    }
+
+    fn child_of(pnr: &str, birth_year: i32, mother: &Person, father: Option<&Person>) -> Person {
+        Person {
+            mother_pnr: Some(mother.pnr.clone()),
+            father_pnr: father.map(|father| father.pnr.clone()),
+            ..person(pnr, birth_year, 'K', mother.household_id)
+        }
+    }
+
+    /// (FAMILIE_ID, FAMILIE_TYPE, HUSTYPE, PLADS) of everyone.
+    fn codes(persons: &[Person]) -> Vec<(String, i8, i8, i8)> {
+        positions_of(persons, 2020)
+            .into_iter()
+            .map(|p| (p.family_id, p.family_type, p.hustype, p.plads))
+            .collect()
+    }
+
+    fn code(family_id: &str, family_type: i8, hustype: i8, plads: i8) -> (String, i8, i8, i8) {
+        (family_id.to_string(), family_type, hustype, plads)
+    }
+
+    #[test]
+    fn married_couple_with_child() {
+        let mut husband = person("h", 1980, 'M', 1);
+        let mut wife = person("w", 1982, 'K', 1);
+        husband.civst = "G";
+        husband.spouse_pnr = Some(wife.pnr.clone());
+        wife.civst = "G";
+        wife.spouse_pnr = Some(husband.pnr.clone());
+        let child = child_of("c", 2012, &wife, Some(&husband));
+
+        // The woman is the reference person of a couple
+        assert_eq!(
+            codes(&[husband, wife, child]),
+            [code("w", 1, 3, 2), code("w", 1, 3, 1), code("w", 1, 3, 3)]
+        );
+    }
+
+    #[test]
+    fn cohabiting_couples_with_and_without_common_children() {
+        let mut man = person("m", 1985, 'M', 1);
+        let mut woman = person("k", 1987, 'K', 1);
+        man.cohabitant_pnr = Some(woman.pnr.clone());
+        woman.cohabitant_pnr = Some(man.pnr.clone());
+        let common = child_of("c", 2015, &woman, Some(&man));
+        assert_eq!(
+            codes(&[man.clone(), woman.clone(), common]),
+            [code("k", 3, 4, 2), code("k", 3, 4, 1), code("k", 3, 4, 3)]
+        );
+
+        let hers = child_of("c", 2010, &woman, None);
+        assert_eq!(
+            codes(&[man, woman, hers]),
+            [code("k", 4, 4, 2), code("k", 4, 4, 1), code("k", 4, 4, 3)]
+        );
+    }
+
+    #[test]
+    fn people_living_alone() {
+        assert_eq!(
+            codes(&[
+                person("m", 1970, 'M', 1),
+                person("k", 1970, 'K', 2),
+                person("b", 2004, 'K', 3),
+            ]),
+            [code("m", 5, 1, 1), code("k", 6, 2, 1), code("b", 7, 5, 1)]
+        );
+    }
+
+    #[test]
+    fn adult_child_at_home_forms_a_family_of_its_own() {
+        let mother = person("k", 1970, 'K', 1);
+        let minor = child_of("c", 2008, &mother, None);
+        let adult = child_of("a", 1998, &mother, None);
+
+        let positions = positions_of(&[mother, minor, adult], 2020);
+        assert_eq!(
+            positions
+                .iter()
+                .map(|p| (p.family_id.as_str(), p.family_type, p.hustype, p.plads))
+                .collect::<Vec<_>>(),
+            [("k", 6, 6, 1), ("k", 6, 6, 3), ("a", 6, 6, 1)]
+        );
+        assert!(positions.iter().all(|p| p.persons_in_household == 3));
+        assert_eq!(positions[0].persons_in_family, 2);
+        assert_eq!(positions[2].persons_in_family, 1);
+        // The adult child lives with the mother, but not as a child
+        assert_eq!(positions[2].fm_mark, 2);
+    }
+}
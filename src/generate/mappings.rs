@@ -1,20 +1,265 @@
+use crate::error::DataGeneratorError;
 use crate::generate::utils::load_mapping;
-use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use once_cell::sync::{Lazy, OnceCell};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// A code in a mapping file: its label, or its label with how common it is
+/// and the years it was in use.
+///
+/// `"G": "Gift (+ separeret)"` and
+/// `"G": { "label": "Gift (+ separeret)", "weight": 38.5, "valid_from": 1990 }`
+/// are both valid. A code without a weight counts as 1, and a code without
+/// `valid_from`/`valid_to` is valid in every year.
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum MappingEntry {
+    Label(String),
+    Detailed {
+        label: String,
+        #[serde(default = "default_weight")]
+        weight: f64,
+        #[serde(default)]
+        valid_from: Option<i32>,
+        #[serde(default)]
+        valid_to: Option<i32>,
+    },
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+impl MappingEntry {
+    pub fn label(&self) -> &str {
+        match self {
+            MappingEntry::Label(label) | MappingEntry::Detailed { label, .. } => label,
+        }
+    }
+
+    pub fn weight(&self) -> f64 {
+        match self {
+            MappingEntry::Label(_) => 1.0,
+            MappingEntry::Detailed { weight, .. } => *weight,
+        }
+    }
+
+    /// The first and last year the code was in use, if it has them.
+    pub fn period(&self) -> (Option<i32>, Option<i32>) {
+        match self {
+            MappingEntry::Label(_) => (None, None),
+            MappingEntry::Detailed {
+                valid_from,
+                valid_to,
+                ..
+            } => (*valid_from, *valid_to),
+        }
+    }
+}
+
+/// The codes of a mapping file in code order, with their labels, weights and
+/// validity periods.
+pub struct CodeList<K> {
+    file_name: String,
+    codes: Vec<K>,
+    labels: Vec<String>,
+    weights: Vec<f64>,
+    periods: Vec<(Option<i32>, Option<i32>)>,
+    distribution: WeightedIndex<f64>,
+}
+
+impl<K: FromStr + Ord + Display> CodeList<K> {
+    /// Loads a mapping file. Fails on codes that don't parse as `K`.
+    pub fn load(file_name: &str) -> Result<Self, DataGeneratorError> {
+        Self::from_entries(file_name, load_mapping(file_name)?)
+    }
+
+    fn from_entries(
+        file_name: &str,
+        entries: BTreeMap<K, MappingEntry>,
+    ) -> Result<Self, DataGeneratorError> {
+        let invalid = |reason: String| DataGeneratorError::InvalidMapping {
+            file: file_name.to_string(),
+            reason,
+        };
+        let mut codes = Vec::with_capacity(entries.len());
+        let mut labels = Vec::with_capacity(entries.len());
+        let mut weights = Vec::with_capacity(entries.len());
+        let mut periods = Vec::with_capacity(entries.len());
+        for (code, entry) in entries {
+            if let (Some(from), Some(to)) = entry.period() {
+                if from > to {
+                    return Err(invalid(format!(
+                        "the code '{}' is valid from {} to {}",
+                        code, from, to
+                    )));
+                }
+            }
+            labels.push(entry.label().to_string());
+            weights.push(entry.weight());
+            periods.push(entry.period());
+            codes.push(code);
+        }
+        let distribution =
+            WeightedIndex::new(&weights).map_err(|e| invalid(format!("invalid weights: {}", e)))?;
+        Ok(CodeList {
+            file_name: file_name.to_string(),
+            codes,
+            labels,
+            weights,
+            periods,
+            distribution,
+        })
+    }
+}
+
+impl<K> CodeList<K> {
+    pub fn codes(&self) -> &[K] {
+        &self.codes
+    }
+
+    /// Every code with its label, whatever years it was in use.
+    pub fn labels(&self) -> impl Iterator<Item = (&K, &str)> {
+        self.codes
+            .iter()
+            .zip(self.labels.iter().map(String::as_str))
+    }
+
+    /// The codes in use in `year` with their labels.
+    pub fn labels_in(&self, year: i32) -> impl Iterator<Item = (&K, &str)> {
+        self.labels()
+            .enumerate()
+            .filter(move |(i, _)| self.is_valid_in(*i, year))
+            .map(|(_, code)| code)
+    }
+
+    /// Whether the code at `index` was in use in `year`.
+    pub fn is_valid_in(&self, index: usize, year: i32) -> bool {
+        let (from, to) = self.periods[index];
+        from.is_none_or(|from| year >= from) && to.is_none_or(|to| year <= to)
+    }
+
+    /// Draws the position of a code, following the weights in the mapping
+    /// file and ignoring validity periods.
+    pub fn sample_index(&self, rng: &mut impl Rng) -> usize {
+        self.distribution.sample(rng)
+    }
+
+    /// Draws a code, following the weights in the mapping file and ignoring
+    /// validity periods. Use [`CodeList::in_year`] for codes that change.
+    pub fn sample(&self, rng: &mut impl Rng) -> &K {
+        &self.codes[self.sample_index(rng)]
+    }
+
+    /// The codes that were in use in `year`. Fails if there are none.
+    pub fn in_year(&self, year: i32) -> Result<CodesInYear<'_, K>, DataGeneratorError> {
+        let weights = (0..self.codes.len()).map(|i| {
+            if self.is_valid_in(i, year) {
+                self.weights[i]
+            } else {
+                0.0
+            }
+        });
+        let distribution =
+            WeightedIndex::new(weights).map_err(|_| DataGeneratorError::InvalidMapping {
+                file: self.file_name.clone(),
+                reason: format!("no code with a weight is valid in {}", year),
+            })?;
+        Ok(CodesInYear {
+            codes: self,
+            distribution,
+        })
+    }
+}
+
+/// The codes of a mapping file that were in use in one year.
+pub struct CodesInYear<'a, K> {
+    codes: &'a CodeList<K>,
+    distribution: WeightedIndex<f64>,
+}
+
+impl<K> CodesInYear<'_, K> {
+    /// Draws the position of a code in the full list, following the weights
+    /// in the mapping file.
+    pub fn sample_index(&self, rng: &mut impl Rng) -> usize {
+        self.distribution.sample(rng)
+    }
+
+    /// Draws a code, following the weights in the mapping file.
+    pub fn sample(&self, rng: &mut impl Rng) -> &K {
+        &self.codes.codes[self.sample_index(rng)]
+    }
+}
+
+/// A mapping file shipped with the generator, read on first use.
+pub struct Mapping<K> {
+    file_name: &'static str,
+    codes: OnceCell<CodeList<K>>,
+}
+
+impl<K: FromStr + Ord + Display> Mapping<K> {
+    const fn new(file_name: &'static str) -> Self {
+        Mapping {
+            file_name,
+            codes: OnceCell::new(),
+        }
+    }
+
+    fn load(&self) -> Result<&CodeList<K>, DataGeneratorError> {
+        self.codes
+            .get_or_try_init(|| CodeList::load(self.file_name))
+    }
+}
+
+impl<K: FromStr + Ord + Display> Deref for Mapping<K> {
+    type Target = CodeList<K>;
+
+    /// Panics if the mapping can't be loaded, which [`load_mappings`] rules
+    /// out before generation starts.
+    fn deref(&self) -> &Self::Target {
+        self.load().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/// Loads every mapping up front so a missing or malformed file is an error
+/// instead of a panic halfway through generation.
+pub fn load_mappings() -> Result<(), DataGeneratorError> {
+    SOCIO13.load()?;
+    CIVST.load()?;
+    FAMILIE_TYPE.load()?;
+    FM_MARK.load()?;
+    HUSTYPE.load()?;
+    PLADS.load()?;
+    REG.load()?;
+    KOM.load()?;
+    STATSB.load()?;
+    JOBKAT.load()?;
+    TILKNYT.load()?;
+    PRE_SOCIO.load()?;
+    BESKST13.load()?;
+    SCD.load()?;
+    Ok(())
+}
 
 // AKM
-pub static SOCIO13: Lazy<HashMap<i32, String>> =
-    Lazy::new(|| load_mapping("mappings/socio13.json"));
+pub static SOCIO13: Mapping<i32> = Mapping::new("socio13.json");
 // BEF
-pub static CIVST: Lazy<HashMap<String, String>> = Lazy::new(|| load_mapping("mappings/civst.json"));
-pub static FM_MARK: Lazy<HashMap<i8, String>> = Lazy::new(|| load_mapping("mappings/fm_mark.json"));
-pub static HUSTYPE: Lazy<HashMap<i8, String>> = Lazy::new(|| load_mapping("mappings/hustype.json"));
-pub static PLADS: Lazy<HashMap<i8, String>> = Lazy::new(|| load_mapping("mappings/plads.json"));
-pub static REG: Lazy<HashMap<i8, String>> = Lazy::new(|| load_mapping("mappings/reg.json"));
-pub static STATSB: Lazy<HashMap<i32, String>> = Lazy::new(|| load_mapping("mappings/statsb.json"));
+pub static CIVST: Mapping<String> = Mapping::new("civst.json");
+pub static FAMILIE_TYPE: Mapping<i8> = Mapping::new("familie_type.json");
+pub static FM_MARK: Mapping<i8> = Mapping::new("fm_mark.json");
+pub static HUSTYPE: Mapping<i8> = Mapping::new("hustype.json");
+pub static PLADS: Mapping<i8> = Mapping::new("plads.json");
+pub static REG: Mapping<i8> = Mapping::new("reg.json");
+pub static KOM: Mapping<i16> = Mapping::new("kom.json");
+pub static STATSB: Mapping<i32> = Mapping::new("statsb.json");
 // IDAN
-pub static JOBKAT: Lazy<HashMap<i8, String>> = Lazy::new(|| load_mapping("mappings/jobkat.json"));
-pub static TILKNYT: Lazy<HashMap<i8, String>> = Lazy::new(|| load_mapping("mappings/tilknyt.json"));
+pub static JOBKAT: Mapping<i8> = Mapping::new("jobkat.json");
+pub static TILKNYT: Mapping<i8> = Mapping::new("tilknyt.json");
 pub static STILL: Lazy<Vec<String>> = Lazy::new(|| {
     vec![
         "01", "02", "03", "04", "05", "11", "12", "13", "14", "19", "20", "31", "32", "33", "34",
@@ -27,10 +272,112 @@ pub static STILL: Lazy<Vec<String>> = Lazy::new(|| {
     .collect()
 });
 // IND
-pub static PRE_SOCIO: Lazy<HashMap<i32, String>> =
-    Lazy::new(|| load_mapping("mappings/pre_socio.json"));
-pub static BESKST13: Lazy<HashMap<i32, String>> =
-    Lazy::new(|| load_mapping("mappings/beskst13.json"));
+pub static PRE_SOCIO: Mapping<i32> = Mapping::new("pre_socio.json");
+pub static BESKST13: Mapping<i32> = Mapping::new("beskst13.json");
+
+//pub static ICD10: Mapping<String> = Mapping::new("icd10.json");
+pub static SCD: Mapping<String> = Mapping::new("scd.json");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::utils::parse_mapping;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::collections::HashSet;
+
+    fn code_list(json: &str) -> Result<CodeList<i16>, DataGeneratorError> {
+        CodeList::from_entries("kom.json", parse_mapping("kom.json", json)?)
+    }
+
+    /// The distinct codes of many draws from the codes in use in `year`.
+    fn drawn(codes: &CodeList<i16>, year: i32) -> HashSet<i16> {
+        let codes = codes.in_year(year).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        (0..1000).map(|_| *codes.sample(&mut rng)).collect()
+    }
+
+    #[test]
+    fn reads_flat_label_mappings() {
+        let codes = code_list(r#"{ "147": "Frederiksberg", "101": "København" }"#).unwrap();
+        assert_eq!(codes.codes(), [101, 147]);
+        assert_eq!(
+            codes.labels().collect::<Vec<_>>(),
+            [(&101, "København"), (&147, "Frederiksberg")]
+        );
+        assert_eq!(drawn(&codes, 1990), HashSet::from([101, 147]));
+    }
+
+    #[test]
+    fn draws_codes_by_weight() {
+        let codes = code_list(
+            r#"{
+                "101": { "label": "København", "weight": 3 },
+                "147": "Frederiksberg",
+                "155": { "label": "Dragør", "weight": 0 }
+            }"#,
+        )
+        .unwrap();
+        let codes = codes.in_year(2020).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let copenhagen = (0..4000).filter(|_| *codes.sample(&mut rng) == 101).count();
+        assert!((2800..3200).contains(&copenhagen), "{}", copenhagen);
+        assert!((0..1000).all(|_| *codes.sample(&mut rng) != 155));
+    }
+
+    #[test]
+    fn keeps_to_the_codes_in_use_in_the_year() {
+        let codes = code_list(
+            r#"{
+                "101": "København",
+                "181": { "label": "Søllerød", "valid_to": 2006 },
+                "230": { "label": "Rudersdal", "valid_from": 2007 }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(drawn(&codes, 2006), HashSet::from([101, 181]));
+        assert_eq!(drawn(&codes, 2007), HashSet::from([101, 230]));
+        let in_2007: Vec<_> = codes.labels_in(2007).map(|(code, _)| *code).collect();
+        assert_eq!(in_2007, [101, 230]);
+        assert_eq!(codes.labels().count(), 3);
+    }
+
+    #[test]
+    fn fails_without_codes_in_use_in_the_year() {
+        let codes = code_list(
+            r#"{
+                "230": { "label": "Rudersdal", "valid_from": 2007 },
+                "101": { "label": "København", "weight": 0 }
+            }"#,
+        )
+        .unwrap();
+        let error = codes.in_year(2006).err().unwrap().to_string();
+        assert!(error.contains("kom.json"), "{}", error);
+        assert!(
+            error.contains("no code with a weight is valid in 2006"),
+            "{}",
+            error
+        );
+    }
 
-//pub static ICD10: Lazy<HashMap<String, String>> = Lazy::new(|| load_mapping("mappings/icd10.json"));
-pub static SCD: Lazy<HashMap<String, String>> = Lazy::new(|| load_mapping("mappings/scd.json"));
+    #[test]
+    fn rejects_malformed_mappings() {
+        for json in [
+            r#"{ "101": "København", "1O1": "Typo" }"#,
+            r#"{ "101": "København", "40000": "Too large" }"#,
+            r#"{ "181": { "label": "Søllerød", "valid_from": 2007, "valid_to": 2006 } }"#,
+            r#"{ "101": { "label": "København", "weight": -1 } }"#,
+            r#"{ "101": { "weight": 1 } }"#,
+        ] {
+            let error = code_list(json).err().map(|e| e.to_string());
+            assert!(
+                error.as_deref().is_some_and(|e| e.contains("kom.json")),
+                "{} gave {:?}",
+                json,
+                error
+            );
+        }
+        let error = code_list(r#"{ "1O1": "Typo" }"#).err().unwrap().to_string();
+        assert!(error.contains("'1O1'"), "{}", error);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::generate::mappings::CodesInYear;
+use chrono::NaiveDate;
+use rand::Rng;
+
+/// Country code for Denmark in `mappings/statsb.json`.
+pub const DENMARK: i32 = 5100;
+
+/// Origin as defined by Statistics Denmark.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    Danish,
+    Immigrant,
+    Descendant,
+}
+
+impl Origin {
+    /// The IE_TYPE code in BEF.
+    pub fn ie_type(self) -> &'static str {
+        match self {
+            Origin::Danish => "1",
+            Origin::Immigrant => "2",
+            Origin::Descendant => "3",
+        }
+    }
+}
+
+/// Whether a migration event moves a person into or out of Denmark.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Immigration,
+    Emigration,
+}
+
+impl Direction {
+    /// The INDUD_KODE code in VNDS.
+    pub fn code(self) -> &'static str {
+        match self {
+            Direction::Immigration => "I",
+            Direction::Emigration => "U",
+        }
+    }
+}
+
+/// A move into or out of Denmark.
+pub struct Migration {
+    pub pnr: String,
+    pub direction: Direction,
+    pub date: NaiveDate,
+}
+
+/// Draws the country of origin of an immigrant from the countries of
+/// `mappings/statsb.json` that existed in the year, by how common they are.
+pub fn draw_origin_country(countries: &CodesInYear<i32>, rng: &mut impl Rng) -> i32 {
+    *countries.sample(rng)
+}
+
+/// Draws the citizenship of someone with a foreign country of origin.
+/// Immigrants become more likely to be naturalised the longer they have lived
+/// in Denmark.
+pub fn draw_citizenship(
+    origin: Origin,
+    origin_country: i32,
+    years_in_denmark: i32,
+    rng: &mut impl Rng,
+) -> i32 {
+    let naturalised_probability = match origin {
+        Origin::Danish => 1.0,
+        Origin::Immigrant => (years_in_denmark as f64 / 30.0).clamp(0.0, 0.6),
+        Origin::Descendant => 0.7,
+    };
+    if rng.gen_bool(naturalised_probability) {
+        DENMARK
+    } else {
+        origin_country
+    }
+}
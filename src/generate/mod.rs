@@ -1,100 +1,522 @@
+pub mod assets;
+pub mod columns;
+pub mod context;
+pub mod family;
 pub mod mappings;
+pub mod migration;
+pub mod municipality;
 pub mod pnr;
+pub mod population;
 pub mod recnum;
+pub mod rng;
+pub mod schema;
 pub mod utils;
 
-use crate::error::DataGeneratorError;
-use crate::registers::*;
-use crate::write::write_dataframe_to_single_parquet;
-use polars::prelude::*;
-use std::path::{Path, PathBuf};
+use crate::config::Config;
+use crate::error::{describe_unsupported, DataGeneratorError, UnsupportedColumn};
+use crate::registers::{resolve_registers, Register};
+use crate::write::{
+    create_sqlite_database, write_dataframe_to_dta, write_dataframe_to_single_csv,
+    write_dataframe_to_single_ipc, write_dataframe_to_single_parquet,
+    write_dataframe_to_single_parquet_with_metadata, write_dataframe_to_sqlite,
+    write_dataframe_to_xport, OutputFormat, SqliteTable, SqliteTables, StataDataset, XportDataset,
+    SQLITE_YEAR_COLUMN,
+};
+use polars::prelude::{DataType, NamedFrom, Series};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
 
+use self::assets::{read_schema, set_asset_dirs};
+use self::context::RegisterContext;
+use self::mappings::{load_mappings, CodeList};
+use self::pnr::PnrPool;
+use self::recnum::RecnumPool;
+use self::rng::SeedStream;
+use self::schema::{parse_dtype, ColumnSpec, Schema};
+
+pub use self::population::Population;
+
+/// The Parquet key-value metadata key holding the labels of coded columns.
+pub const CODE_LABELS_KEY: &str = "code_labels";
+/// The Parquet key-value metadata key holding the descriptions of the columns.
+pub const COLUMN_DESCRIPTIONS_KEY: &str = "column_descriptions";
+/// The database in the output directory that SQLite output of all registers goes into.
+pub const SQLITE_DATABASE_FILE: &str = "registers.sqlite";
 pub use self::utils::*;
 
-pub fn generate_data(
-    registers: &[String],
-    no_rows: usize,
-    years: &[i32],
-    output_dir: &Path,
-) -> Result<(), DataGeneratorError> {
-    for register in registers {
-        println!("Generating data for register: {}", register);
-        generate_from_json(register, no_rows, years, output_dir)?;
+pub fn generate_data(config: &Config, output_dir: &Path) -> Result<(), DataGeneratorError> {
+    set_asset_dirs(config.schema_dir.clone(), config.mapping_dir.clone());
+    load_mappings()?;
+    let registers = resolve_registers(&config.registers)?;
+    let schemas = registers
+        .iter()
+        .map(|register| load_schema(*register))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Find every column nothing can produce before spending time on generation
+    let unsupported: Vec<UnsupportedColumn> = registers
+        .iter()
+        .zip(&schemas)
+        .flat_map(|(register, schema)| unsupported_columns(*register, schema, config.years))
+        .collect();
+    if !unsupported.is_empty() {
+        if !config.fill_unsupported {
+            return Err(DataGeneratorError::UnsupportedColumns(unsupported));
+        }
+        println!(
+            "Writing nulls for columns without a generator: {}",
+            describe_unsupported(&unsupported)
+        );
     }
-    Ok(())
-}
 
-pub fn generate_from_json(
-    register: &str,
-    no_rows: usize,
-    years: &[i32],
-    output_dir: &Path,
-) -> Result<(), DataGeneratorError> {
-    let schema_dir = PathBuf::from("schemas");
-    let json_file = schema_dir.join(format!("{}.json", register));
+    // All registers share one database, which starts out empty
+    if config.format == OutputFormat::Sqlite {
+        std::fs::create_dir_all(output_dir)?;
+        create_sqlite_database(&output_dir.join(SQLITE_DATABASE_FILE), config.overwrite)?;
+    }
 
-    if !json_file.exists() {
-        return Err(DataGeneratorError::Other(format!(
-            "Schema file for register '{}' not found at path: {}",
+    let seed = SeedStream::new(config.seed);
+    // Build the population for every year up front so all registers share it
+    let populations = generate_populations(
+        config.years,
+        config.rows,
+        config.panel,
+        config.allow_non_modulus11,
+        seed,
+    )?;
+
+    let recnums = Mutex::new(RecnumPool::new());
+    for (register, schema) in registers.into_iter().zip(&schemas) {
+        println!("Generating data for register: {}", register.name());
+        generate_from_json(
             register,
-            json_file.display()
-        )));
+            schema,
+            config,
+            &populations,
+            output_dir,
+            seed.derive(register.name()),
+            &recnums,
+        )?;
     }
+    Ok(())
+}
 
-    let json = load_json(&json_file).map_err(|e| {
+/// The population at the end of every year.
+///
+/// In panel mode every year after the first carries the previous one
+/// forward. Otherwise every year is drawn on its own with PNRs of its own, so
+/// a year is the same whatever other years are generated with it.
+pub fn generate_populations(
+    years: (i32, i32),
+    size: usize,
+    panel: bool,
+    allow_non_modulus11: bool,
+    seed: SeedStream,
+) -> Result<Vec<Population>, DataGeneratorError> {
+    let mut pnr_pool = PnrPool::new(seed.derive("pnr"), allow_non_modulus11);
+    let mut populations: Vec<Population> = Vec::new();
+    for year in years.0..=years.1 {
+        println!("Generating population for year: {}", year);
+        let population = match populations.last() {
+            Some(previous) if panel => {
+                previous.advance(&mut pnr_pool, seed.derive("transitions"))?
+            }
+            // Start from the end of the year before, so that the year still
+            // has its births and deaths
+            _ => {
+                if !panel {
+                    pnr_pool = PnrPool::new(
+                        seed.derive("pnr").derive(year.to_string()),
+                        allow_non_modulus11,
+                    );
+                }
+                Population::generate(year - 1, size, &mut pnr_pool, seed.derive("population"))?
+                    .advance(&mut pnr_pool, seed.derive("transitions"))?
+            }
+        };
+        populations.push(population);
+    }
+    Ok(populations)
+}
+
+/// Loads and parses the schema of a register, from the schema directory if
+/// it has one and otherwise from the embedded schemas.
+pub fn load_schema(register: &dyn Register) -> Result<Schema, DataGeneratorError> {
+    let json = read_schema(&register.schema_file())?;
+    let schema: Schema = serde_json::from_str(&json).map_err(|e| {
         DataGeneratorError::Other(format!(
             "Failed to load JSON for register '{}': {}",
-            register, e
+            register.name(),
+            e
         ))
     })?;
-
-    // Create directory for the register
-    let register_dir = output_dir.join(register);
-    std::fs::create_dir_all(&register_dir).map_err(|e| {
+    schema.check_periods().map_err(|e| {
         DataGeneratorError::Other(format!(
-            "Failed to create directory for register '{}': {}",
-            register, e
+            "Invalid schema for register '{}': {}",
+            register.name(),
+            e
         ))
     })?;
 
-    for &year in years {
-        let mut columns = Vec::new();
-
-        if let Some(columns_def) = json.get("columns").and_then(|c| c.as_array()) {
-            for col_def in columns_def {
-                let col_name = col_def
-                    .get("name")
-                    .and_then(|n| n.as_str())
-                    .unwrap_or_default();
-
-                let series = match register {
-                    "akm" => create_akm_series(col_name, no_rows),
-                    "idan" => create_idan_series(col_name, no_rows),
-                    "ind" => create_ind_series(col_name, no_rows),
-                    "uddf" => create_uddf_series(col_name, no_rows),
-                    "lpr3_diagnoser" => create_lpr3_diagnoser_series(col_name, no_rows),
-                    "lpr3_kontakter" => create_lpr3_kontakter_series(col_name, no_rows, year),
-                    "lpr_adm" => create_lpr_adm_series(col_name, no_rows, year),
-                    "lpr_bes" => create_lpr_bes_series(col_name, no_rows, year),
-                    "lpr_diag" => create_lpr_diag_series(col_name, no_rows, year),
-                    _ => create_bef_series(col_name, no_rows, year),
-                };
-                columns.push(series);
-            }
+    // Catch missing or malformed mappings before anything is generated
+    for column in &schema.columns {
+        if let Some(file) = column.labels_file() {
+            CodeList::<String>::load(file)?;
+        }
+    }
+    Ok(schema)
+}
+
+/// The labels of the codes in use in a register-year as a JSON object of
+/// code-to-label objects by column, e.g. `{"CIVST": {"G": "Gift (+ separeret)"}}`.
+pub fn code_labels(schema: &Schema, year: i32) -> Result<String, DataGeneratorError> {
+    let labels: serde_json::Map<_, _> = column_code_labels(schema, year)?
+        .into_iter()
+        .map(|(column, codes)| {
+            let codes = codes
+                .into_iter()
+                .map(|(code, label)| (code, label.into()))
+                .collect();
+            (column, serde_json::Value::Object(codes))
+        })
+        .collect();
+    Ok(serde_json::Value::Object(labels).to_string())
+}
+
+/// The Parquet key-value metadata written with `--labels`: the labels of the
+/// codes in use in a register-year, and the descriptions of its columns as a
+/// JSON object by column.
+pub fn label_metadata(
+    schema: &Schema,
+    year: i32,
+) -> Result<Vec<(String, String)>, DataGeneratorError> {
+    let descriptions: serde_json::Map<_, _> = column_descriptions(schema, year)
+        .into_iter()
+        .map(|(column, description)| (column, description.into()))
+        .collect();
+    Ok(vec![
+        (CODE_LABELS_KEY.to_string(), code_labels(schema, year)?),
+        (
+            COLUMN_DESCRIPTIONS_KEY.to_string(),
+            serde_json::Value::Object(descriptions).to_string(),
+        ),
+    ])
+}
+
+/// The codes in use in a register-year with their labels, in code order, for
+/// every coded column.
+pub fn column_code_labels(
+    schema: &Schema,
+    year: i32,
+) -> Result<HashMap<String, Vec<(String, String)>>, DataGeneratorError> {
+    let mut labels = HashMap::new();
+    for column in schema.columns_for(year) {
+        if let Some(file) = column.labels_file() {
+            let codes = CodeList::<String>::load(file)?
+                .labels_in(year)
+                .map(|(code, label)| (code.clone(), label.to_string()))
+                .collect();
+            labels.insert(column.name.clone(), codes);
         }
+    }
+    Ok(labels)
+}
 
-        let df = DataFrame::new(columns)?;
+/// The descriptions of the columns of a register-year by column name.
+pub fn column_descriptions(schema: &Schema, year: i32) -> HashMap<String, String> {
+    schema
+        .columns_for(year)
+        .filter_map(|column| Some((column.name.clone(), column.description.clone()?)))
+        .collect()
+}
+
+/// The names of schema columns with their declared types.
+fn column_types<'a>(columns: impl Iterator<Item = &'a ColumnSpec>) -> Vec<(String, DataType)> {
+    columns
+        .filter_map(|column| Some((column.name.clone(), parse_dtype(&column.type_name)?)))
+        .collect()
+}
 
-        // Write DataFrame to a Parquet file
-        let file_name = if register == "bef" {
-            format!("{}12.parquet", year)
-        } else {
-            format!("{}.parquet", year)
+/// The schema columns of a register that neither declare a generator nor
+/// are produced by the register's code, for every year it is generated.
+pub fn unsupported_columns(
+    register: &dyn Register,
+    schema: &Schema,
+    years: (i32, i32),
+) -> Vec<UnsupportedColumn> {
+    let mut unsupported = Vec::new();
+    for column in &schema.columns {
+        if column.generator.is_some() || register.columns().contains(&column.name.as_str()) {
+            continue;
+        }
+        let generated_in =
+            |year: &i32| register.years().contains(year) && column.is_valid_in(*year);
+        for year in (years.0..=years.1).filter(generated_in) {
+            unsupported.push(UnsupportedColumn {
+                register: register.name().to_string(),
+                column: column.name.clone(),
+                year,
+            });
+        }
+    }
+    unsupported
+}
+
+/// The person behind each row of a register-year, and for registers with
+/// dependencies the key of the contact the row belongs to.
+fn register_rows(
+    register: &dyn Register,
+    population: &Population,
+    no_rows: usize,
+    recnums: &Mutex<RecnumPool>,
+    seed: SeedStream,
+) -> (Vec<String>, Vec<String>) {
+    if register.dependencies().is_empty() {
+        let pnrs = register.pnrs(population, no_rows, seed.derive("pnrs"));
+        return (pnrs, Vec::new());
+    }
+    recnums
+        .lock()
+        .unwrap()
+        .sample_contacts(
+            register.dependencies(),
+            population.year(),
+            no_rows,
+            seed.derive("contacts"),
+        )
+        .into_iter()
+        .map(|contact| (contact.pnr, contact.key))
+        .unzip()
+}
+
+pub fn generate_from_json(
+    register: &dyn Register,
+    schema: &Schema,
+    config: &Config,
+    populations: &[Population],
+    output_dir: &Path,
+    seed: SeedStream,
+    recnums: &Mutex<RecnumPool>,
+) -> Result<(), DataGeneratorError> {
+    let name = register.name();
+
+    // Create directory for the register, unless it goes into the database
+    let register_dir = output_dir.join(name);
+    if config.format != OutputFormat::Sqlite {
+        std::fs::create_dir_all(&register_dir).map_err(|e| {
+            DataGeneratorError::Other(format!(
+                "Failed to create directory for register '{}': {}",
+                name, e
+            ))
+        })?;
+    }
+
+    for population in populations {
+        let year = population.year();
+        if !register.years().contains(&year) {
+            println!(
+                "Skipping register '{}' for year {}, which it doesn't cover",
+                name, year
+            );
+            continue;
+        }
+        let seed = seed.derive(year.to_string());
+        let (pnrs, contacts) = register_rows(register, population, config.rows, recnums, seed);
+        let ctx = RegisterContext {
+            year,
+            population,
+            pnrs,
+            contacts,
+            seed,
+            recnums,
+            fill_unsupported: config.fill_unsupported,
         };
-        let file_path = register_dir.join(file_name);
-        write_dataframe_to_single_parquet(&mut df.clone(), &file_path)?;
-        println!("Generated data for register '{}' year {}", register, year);
+        let mut df = register.generate_frame(schema, &ctx)?;
+
+        let file_path = register_dir.join(format!(
+            "{}.{}",
+            register.file_stem(year),
+            config.file_extension()
+        ));
+        match config.format {
+            OutputFormat::Parquet if config.labels => {
+                let metadata = label_metadata(schema, year)?;
+                write_dataframe_to_single_parquet_with_metadata(&df, &file_path, &metadata)?;
+            }
+            OutputFormat::Parquet => write_dataframe_to_single_parquet(&mut df, &file_path)?,
+            OutputFormat::Csv => write_dataframe_to_single_csv(&df, &file_path, &config.csv)?,
+            OutputFormat::Ipc => {
+                write_dataframe_to_single_ipc(&mut df, &file_path, config.ipc_compression)?
+            }
+            OutputFormat::Xport => {
+                let dataset = XportDataset {
+                    name: name.to_string(),
+                    label: format!("{} {}", name.to_uppercase(), year),
+                    column_labels: column_descriptions(schema, year),
+                };
+                write_dataframe_to_xport(&df, &file_path, &dataset, &config.xport)?
+            }
+            OutputFormat::Stata => {
+                let dataset = StataDataset {
+                    label: format!("{} {}", name.to_uppercase(), year),
+                    column_labels: column_descriptions(schema, year),
+                    value_labels: column_code_labels(schema, year)?,
+                };
+                write_dataframe_to_dta(&df, &file_path, &dataset)?
+            }
+            OutputFormat::Sqlite => {
+                let table = match config.sqlite_tables {
+                    SqliteTables::Register => {
+                        df.with_column(Series::new(
+                            SQLITE_YEAR_COLUMN.into(),
+                            vec![year; df.height()],
+                        ))?;
+                        // The table holds every column of the years generated
+                        let generated_years = (config.years.0..=config.years.1)
+                            .filter(|year| register.years().contains(year))
+                            .collect::<Vec<_>>();
+                        let mut columns = vec![(SQLITE_YEAR_COLUMN.to_string(), DataType::Int32)];
+                        columns.extend(column_types(schema.columns.iter().filter(|column| {
+                            generated_years.iter().any(|year| column.is_valid_in(*year))
+                        })));
+                        SqliteTable::new(name.to_string(), columns)?
+                    }
+                    SqliteTables::Year => SqliteTable::new(
+                        format!("{}_{}", name, year),
+                        column_types(schema.columns_for(year)),
+                    )?,
+                };
+                write_dataframe_to_sqlite(&df, &output_dir.join(SQLITE_DATABASE_FILE), &table)?
+            }
+        }
+        println!("Generated data for register '{}' year {}", name, year);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn pnrs(population: &Population) -> Vec<&str> {
+        population
+            .persons()
+            .iter()
+            .map(|person| person.pnr.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn draws_every_year_on_its_own_without_panel() {
+        let seed = SeedStream::new(7);
+        let years = generate_populations((2017, 2020), 500, false, false, seed).unwrap();
+        let last_alone = generate_populations((2020, 2020), 500, false, false, seed).unwrap();
+        assert_eq!(years.len(), 4);
+        assert!(years
+            .iter()
+            .all(|population| population.persons().len() >= 500));
+        // The PNRs of earlier years don't use up those of later ones
+        assert_eq!(pnrs(&years[3]), pnrs(&last_alone[0]));
+    }
+
+    #[test]
+    fn writes_labels_and_descriptions_to_parquet_metadata() {
+        let schema: Schema = serde_json::from_str(
+            r#"{ "columns": [
+                { "name": "PNR", "type": "Utf8", "description": "Personnummer" },
+                { "name": "CIVST", "type": "Utf8", "labels": "civst.json" }
+            ] }"#,
+        )
+        .unwrap();
+        let df = polars::prelude::DataFrame::new(vec![
+            Series::new("PNR".into(), ["0101901234"]),
+            Series::new("CIVST".into(), ["G"]),
+        ])
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("cdef-{}-labels.parquet", std::process::id()));
+        let metadata = label_metadata(&schema, 2020).unwrap();
+        write_dataframe_to_single_parquet_with_metadata(&df, &path, &metadata).unwrap();
+        let file_metadata =
+            polars_parquet::read::read_metadata(&mut std::fs::File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let written: HashMap<String, serde_json::Value> = file_metadata
+            .key_value_metadata()
+            .iter()
+            .flatten()
+            .filter_map(|pair| {
+                Some((
+                    pair.key.clone(),
+                    serde_json::from_str(pair.value.as_ref()?).ok()?,
+                ))
+            })
+            .collect();
+        assert_eq!(written[CODE_LABELS_KEY]["CIVST"]["G"], "Gift (+ separeret)");
+        assert!(written[CODE_LABELS_KEY].get("PNR").is_none());
+        assert_eq!(
+            written[COLUMN_DESCRIPTIONS_KEY],
+            serde_json::json!({ "PNR": "Personnummer" })
+        );
+    }
+
+    /// Generates a year of the given registers on a pool of `threads` threads.
+    fn frames_on_threads(names: &[&str], threads: usize) -> Vec<polars::prelude::DataFrame> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let seed = SeedStream::new(11);
+            let populations = generate_populations((2018, 2018), 300, false, false, seed).unwrap();
+            let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+            let recnums = Mutex::new(RecnumPool::new());
+            resolve_registers(&names)
+                .unwrap()
+                .into_iter()
+                .map(|register| {
+                    let seed = seed.derive(register.name()).derive("2018");
+                    let (pnrs, contacts) =
+                        register_rows(register, &populations[0], 300, &recnums, seed);
+                    let ctx = RegisterContext {
+                        year: 2018,
+                        population: &populations[0],
+                        pnrs,
+                        contacts,
+                        seed,
+                        recnums: &recnums,
+                        fill_unsupported: true,
+                    };
+                    register
+                        .generate_frame(&load_schema(register).unwrap(), &ctx)
+                        .unwrap()
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn output_does_not_depend_on_the_number_of_threads() {
+        let names = ["bef", "ind", "akm", "lpr_adm", "lpr_diag"];
+        let one = frames_on_threads(&names, 1);
+        let four = frames_on_threads(&names, 4);
+        for ((name, one), four) in names.iter().zip(&one).zip(&four) {
+            assert!(one.height() > 0, "{} is empty", name);
+            assert!(one.equals_missing(four), "{} differs", name);
+        }
+    }
+
+    #[test]
+    fn carries_people_forward_in_panel() {
+        let seed = SeedStream::new(7);
+        let years = generate_populations((2019, 2020), 500, true, false, seed).unwrap();
+        let first: HashSet<&str> = pnrs(&years[0]).into_iter().collect();
+        let stayed = pnrs(&years[1])
+            .into_iter()
+            .filter(|pnr| first.contains(pnr))
+            .count();
+        assert!(stayed > 450, "{}", stayed);
+    }
+}
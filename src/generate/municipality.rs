@@ -0,0 +1,44 @@
+/// The first year of the municipalities and regions of the 2007 reform.
+pub const MUNICIPAL_REFORM_YEAR: i32 = 2007;
+
+/// The municipality of an address in `year`: the municipality a
+/// municipality abolished in the reform was merged into, and otherwise the
+/// municipality itself. See `mappings/kom.json`.
+pub fn municipality_in(kom: i16, year: i32) -> i16 {
+    if year < MUNICIPAL_REFORM_YEAR {
+        return kom;
+    }
+    match kom {
+        171 | 235 | 237 => 240,
+        181 | 203 => 230,
+        189 | 205 => 190,
+        207 | 227 => 210,
+        209 | 225 | 229 | 233 => 250,
+        211 | 221 => 260,
+        213 | 215 => 270,
+        231 => 219,
+        251 | 257 | 261 => 350,
+        255 | 263 => 265,
+        267 => 259,
+        271 => 336,
+        631 => 630,
+        731 => 730,
+        743 => 740,
+        _ => kom,
+    }
+}
+
+/// The region of a municipality, see `mappings/reg.json`. Municipalities
+/// abolished in the reform belong to the region of the municipality they
+/// were merged into.
+pub fn region_of(kom: i16) -> i8 {
+    match municipality_in(kom, MUNICIPAL_REFORM_YEAR) {
+        253 | 259 | 265 | 269 => 85,
+        101..=270 | 400 | 411 => 84,
+        306..=390 => 85,
+        615 | 657..=766 | 779 | 791 => 82,
+        410..=630 => 83,
+        773 | 787 | 810..=860 => 81,
+        _ => 0,
+    }
+}
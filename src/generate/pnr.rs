@@ -1,190 +1,320 @@
+use crate::error::DataGeneratorError;
+use crate::generate::rng::SeedStream;
 use chrono::{Datelike, NaiveDate};
-use once_cell::sync::Lazy;
-use rand::seq::IteratorRandom;
 use rand::seq::SliceRandom;
-use rand::Rng;
 use std::collections::HashMap;
-use std::sync::Mutex;
 
-static PNR_POOL: Lazy<Mutex<PnrPool>> = Lazy::new(|| Mutex::new(PnrPool::new()));
+/// First and last birth year that can be encoded in a CPR number.
+pub const CPR_FIRST_YEAR: i32 = 1858;
+pub const CPR_LAST_YEAR: i32 = 2057;
 
-#[derive(Clone)]
-struct Person {
-    pnr: String,
-    birth_date: NaiveDate,
-    gender: char,
-    mother_pnr: Option<String>,
-    father_pnr: Option<String>,
+/// Weights for the modulus-11 check over the ten digits of a CPR number.
+const MODULUS11_WEIGHTS: [u32; 10] = [4, 3, 2, 7, 6, 5, 4, 3, 2, 1];
+
+/// CPR numbers without a valid modulus-11 check are issued for people born on
+/// or after this date.
+pub fn modulus11_exemption_date() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2007, 10, 1).unwrap()
 }
 
-struct PnrPool {
-    persons: HashMap<String, Person>,
-    years: Vec<i32>,
-    min_parent_age: i32,
-    max_parent_age: i32,
+/// The 7th digits that encode the given birth year.
+fn seventh_digits_for_year(year: i32) -> &'static [u32] {
+    match year {
+        1858..=1899 => &[5, 6, 7, 8],
+        1900..=1936 => &[0, 1, 2, 3],
+        1937..=1999 => &[0, 1, 2, 3, 4, 9],
+        2000..=2036 => &[4, 5, 6, 7, 8, 9],
+        2037..=2057 => &[5, 6, 7, 8],
+        _ => &[],
+    }
 }
 
-impl PnrPool {
-    fn new() -> Self {
-        PnrPool {
-            persons: HashMap::new(),
-            years: Vec::new(),
-            min_parent_age: 18,
-            max_parent_age: 50,
-        }
+/// The century encoded by the 7th digit together with the two-digit birth year.
+fn century_from_digit(seventh_digit: u32, two_digit_year: i32) -> Option<i32> {
+    match seventh_digit {
+        0..=3 => Some(1900),
+        4 | 9 if two_digit_year <= 36 => Some(2000),
+        4 | 9 => Some(1900),
+        5..=8 if two_digit_year <= 57 => Some(2000),
+        5..=8 => Some(1800),
+        _ => None,
     }
+}
 
-    fn generate_pnr(&self, birth_date: NaiveDate, gender: char) -> String {
-        let mut rng = rand::thread_rng();
-        let day = birth_date.day();
-        let month = birth_date.month();
-        let year = birth_date.year() % 100;
-        let century = birth_date.year() / 100;
-
-        let seventh_digit = match century {
-            18 => rng.gen_range(5..8),
-            19 => {
-                if birth_date.year() < 1937 {
-                    rng.gen_range(0..4)
-                } else {
-                    rng.gen_range(4..10)
-                }
-            }
-            20 => rng.gen_range(0..4),
-            _ => rng.gen_range(4..10),
-        };
+fn sex_from_serial(serial: u32) -> char {
+    if serial % 2 == 1 {
+        'M'
+    } else {
+        'K'
+    }
+}
 
-        let last_three_digits = loop {
-            let digits = rng.gen_range(0..999);
-            if (gender == 'M' && digits % 2 == 1) || (gender == 'K' && digits % 2 == 0) {
-                break digits;
-            }
-        };
+/// The ten digits of a CPR number, with only the hyphen after the birth date
+/// allowed besides them.
+fn cpr_digits(pnr: &str) -> Option<Vec<u32>> {
+    let bytes = pnr.as_bytes();
+    let digits = match bytes.len() {
+        10 => bytes.to_vec(),
+        11 if bytes[6] == b'-' => [&bytes[..6], &bytes[7..]].concat(),
+        _ => return None,
+    };
+    digits
+        .iter()
+        .map(|&b| b.is_ascii_digit().then(|| u32::from(b - b'0')))
+        .collect()
+}
 
-        format!(
-            "{:02}{:02}{:02}-{}{:03}",
-            day, month, year, seventh_digit, last_three_digits
-        )
-    }
+fn is_modulus11(pnr: &str) -> bool {
+    cpr_digits(pnr).is_some_and(|digits| {
+        digits
+            .iter()
+            .zip(MODULUS11_WEIGHTS)
+            .map(|(d, w)| d * w)
+            .sum::<u32>()
+            % 11
+            == 0
+    })
+}
 
-    fn add_person(
-        &mut self,
-        birth_year: i32,
-        mother_pnr: Option<String>,
-        father_pnr: Option<String>,
-    ) -> String {
-        let mut rng = rand::thread_rng();
-        let birth_date =
-            NaiveDate::from_ymd_opt(birth_year, rng.gen_range(1..13), rng.gen_range(1..29))
-                .unwrap();
-        let gender = if rng.gen_bool(0.5) { 'M' } else { 'K' };
-
-        let pnr = self.generate_pnr(birth_date, gender);
-
-        let person = Person {
-            pnr: pnr.clone(),
-            birth_date,
-            gender,
-            mother_pnr,
-            father_pnr,
-        };
+/// Every serial (the last four digits) that forms a valid CPR number for the
+/// birth date and sex.
+fn valid_serials(birth_date: NaiveDate, gender: char, require_modulus11: bool) -> Vec<u16> {
+    let date_digits = [
+        birth_date.day() / 10,
+        birth_date.day() % 10,
+        birth_date.month() / 10,
+        birth_date.month() % 10,
+        (birth_date.year() % 100) as u32 / 10,
+        (birth_date.year() % 100) as u32 % 10,
+    ];
+    let date_sum: u32 = date_digits
+        .iter()
+        .zip(MODULUS11_WEIGHTS)
+        .map(|(d, w)| d * w)
+        .sum();
+    let serial_sum = |serial: u32| {
+        [
+            serial / 1000,
+            serial / 100 % 10,
+            serial / 10 % 10,
+            serial % 10,
+        ]
+        .iter()
+        .zip(&MODULUS11_WEIGHTS[6..])
+        .map(|(d, w)| d * w)
+        .sum::<u32>()
+    };
 
-        self.persons.insert(pnr.clone(), person);
-        pnr
-    }
+    seventh_digits_for_year(birth_date.year())
+        .iter()
+        .flat_map(|&seventh_digit| seventh_digit * 1000..(seventh_digit + 1) * 1000)
+        .filter(|&serial| sex_from_serial(serial) == gender)
+        .filter(|&serial| !require_modulus11 || (date_sum + serial_sum(serial)).is_multiple_of(11))
+        .map(|serial| serial as u16)
+        .collect()
+}
 
-    fn generate_year(&mut self, year: i32) {
-        if self.years.contains(&year) {
-            return;
-        }
+/// The information encoded in a CPR number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedPnr {
+    pub birth_date: NaiveDate,
+    /// 'M' for odd and 'K' for even last digits.
+    pub sex: char,
+    /// The first year of the birth century, e.g. 1900.
+    pub century: i32,
+    pub modulus11: bool,
+}
 
-        let mut rng = rand::thread_rng();
-        let num_births = rng.gen_range(55000..65001);
+/// Decodes a CPR number in either `DDMMYYSSSS` or `DDMMYY-SSSS` form.
+///
+/// Only the structure is checked; use [`validate_pnr`] to also require a
+/// valid modulus-11 check where it applies.
+pub fn decode_pnr(pnr: &str) -> Result<DecodedPnr, DataGeneratorError> {
+    let invalid = |reason: &str| DataGeneratorError::InvalidPnr(format!("{}: {}", pnr, reason));
 
-        // Generate parents first
-        for _ in 0..num_births * 2 {
-            let parent_birth_year = year - rng.gen_range(self.min_parent_age..=self.max_parent_age);
-            self.add_person(parent_birth_year, None, None);
+    let digits = cpr_digits(pnr).ok_or_else(|| invalid("expected DDMMYYSSSS or DDMMYY-SSSS"))?;
+    let day = digits[0] * 10 + digits[1];
+    let month = digits[2] * 10 + digits[3];
+    let two_digit_year = (digits[4] * 10 + digits[5]) as i32;
+    let century = century_from_digit(digits[6], two_digit_year)
+        .ok_or_else(|| invalid("invalid century digit"))?;
+    let birth_date = NaiveDate::from_ymd_opt(century + two_digit_year, month, day)
+        .ok_or_else(|| invalid("invalid birth date"))?;
+
+    Ok(DecodedPnr {
+        birth_date,
+        sex: sex_from_serial(digits[9]),
+        century,
+        modulus11: is_modulus11(pnr),
+    })
+}
+
+/// The CPRTJEK code of a CPR number: 1 if it passes the modulus-11 check, and
+/// 0 if it was issued without one or can't be decoded.
+pub fn cprtjek(pnr: &str) -> i8 {
+    decode_pnr(pnr).is_ok_and(|pnr| pnr.modulus11) as i8
+}
+
+/// Decodes a CPR number and checks that it satisfies modulus-11, unless the
+/// person is born on or after the 2007 exemption date.
+///
+/// The CPR office has issued numbers without modulus-11 since 1 October 2007
+/// regardless of birth date, e.g. to immigrants, but the issue date isn't
+/// part of the number. Such numbers of people born before the exemption
+/// date are rejected here, even though they're valid.
+pub fn validate_pnr(pnr: &str) -> Result<DecodedPnr, DataGeneratorError> {
+    let decoded = decode_pnr(pnr)?;
+    if !decoded.modulus11 && decoded.birth_date < modulus11_exemption_date() {
+        return Err(DataGeneratorError::InvalidPnr(format!(
+            "{}: fails the modulus-11 check",
+            pnr
+        )));
+    }
+    Ok(decoded)
+}
+
+/// Hands out unique PNRs, drawing from the free serials of each birth date
+/// and sex so that no PNR is ever given to two people.
+pub struct PnrPool {
+    // Shuffled serials not yet handed out, per birth date and sex
+    free_serials: HashMap<(NaiveDate, char), Vec<u16>>,
+    seed: SeedStream,
+    allow_non_modulus11: bool,
+}
+
+impl PnrPool {
+    /// `allow_non_modulus11` permits CPR numbers without a valid modulus-11
+    /// check for people born after the exemption date.
+    pub fn new(seed: SeedStream, allow_non_modulus11: bool) -> Self {
+        PnrPool {
+            free_serials: HashMap::new(),
+            seed,
+            allow_non_modulus11,
         }
+    }
 
-        // Then generate children with parents
-        for _ in 0..num_births {
-            let mother_pnr = self.get_random_adult_pnr('K', year);
-            let father_pnr = self.get_random_adult_pnr('M', year);
-            self.add_person(year, mother_pnr, father_pnr);
+    pub fn allocate_pnr(
+        &mut self,
+        birth_date: NaiveDate,
+        gender: char,
+    ) -> Result<String, DataGeneratorError> {
+        if seventh_digits_for_year(birth_date.year()).is_empty() {
+            return Err(DataGeneratorError::InvalidPnr(format!(
+                "birth date {} is outside the CPR range {}-{}",
+                birth_date, CPR_FIRST_YEAR, CPR_LAST_YEAR
+            )));
         }
 
-        self.years.push(year);
+        let require_modulus11 =
+            !(self.allow_non_modulus11 && birth_date >= modulus11_exemption_date());
+        let seed = self.seed.derive(format!("{}{}", birth_date, gender));
+        let serial = self
+            .free_serials
+            .entry((birth_date, gender))
+            .or_insert_with(|| {
+                let mut serials = valid_serials(birth_date, gender, require_modulus11);
+                serials.shuffle(&mut seed.rng(0));
+                serials
+            })
+            .pop()
+            .ok_or(DataGeneratorError::PnrExhausted {
+                birth_date,
+                sex: gender,
+            })?;
+
+        Ok(format!("{}-{:04}", birth_date.format("%d%m%y"), serial))
     }
+}
 
-    fn get_random_adult_pnr(&self, gender: char, current_year: i32) -> Option<String> {
-        let adults: Vec<&Person> = self
-            .persons
-            .values()
-            .filter(|p| {
-                p.gender == gender
-                    && (current_year - p.birth_date.year()) >= self.min_parent_age
-                    && (current_year - p.birth_date.year()) <= self.max_parent_age
-            })
-            .collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        adults
-            .choose(&mut rand::thread_rng())
-            .map(|p| p.pnr.clone())
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
     }
 
-    fn get_random_pnr(&self) -> Option<String> {
-        self.persons.keys().choose(&mut rand::thread_rng()).cloned()
+    #[test]
+    fn decodes_birth_date_sex_and_modulus11() {
+        let decoded = decode_pnr("070761-4285").unwrap();
+        assert_eq!(decoded.birth_date, date(1961, 7, 7));
+        assert_eq!(decoded.sex, 'M');
+        assert_eq!(decoded.century, 1900);
+        assert!(decoded.modulus11);
+        assert_eq!(decode_pnr("0707614285").unwrap(), decoded);
+        assert!(!decode_pnr("070761-4286").unwrap().modulus11);
     }
 
-    fn get_or_create_pnr(&mut self, birth_date: NaiveDate) -> String {
-        let year = birth_date.year();
-        self.generate_year(year);
+    #[test]
+    fn cprtjek_flags_modulus11() {
+        assert_eq!(cprtjek("070761-4285"), 1);
+        assert_eq!(cprtjek("070761-4286"), 0);
+        assert_eq!(cprtjek("010190+1234"), 0);
+    }
 
-        let gender = if rand::thread_rng().gen_bool(0.5) {
-            'M'
-        } else {
-            'K'
-        };
-        let pnr = self.generate_pnr(birth_date, gender);
+    #[test]
+    fn decodes_century_from_seventh_digit() {
+        let year = |pnr: &str| decode_pnr(pnr).unwrap().birth_date.year();
+        assert_eq!(year("010137-3000"), 1937);
+        assert_eq!(year("010136-4000"), 2036);
+        assert_eq!(year("010137-4000"), 1937);
+        assert_eq!(year("010157-5000"), 2057);
+        assert_eq!(year("010158-5000"), 1858);
+        assert_eq!(year("010136-9000"), 2036);
+        assert_eq!(year("010199-9000"), 1999);
+    }
 
-        if !self.persons.contains_key(&pnr) {
-            let person = Person {
-                pnr: pnr.clone(),
-                birth_date,
-                gender,
-                mother_pnr: None,
-                father_pnr: None,
-            };
-            self.persons.insert(pnr.clone(), person);
+    #[test]
+    fn rejects_malformed_numbers() {
+        for pnr in [
+            "010190-12-4",
+            "0101901234-",
+            "010190+1234",
+            "010190-123",
+            "01019012345",
+            "01O190-1234",
+            "310290-1234",
+            "",
+        ] {
+            assert!(
+                matches!(decode_pnr(pnr), Err(DataGeneratorError::InvalidPnr(_))),
+                "{} was accepted",
+                pnr
+            );
         }
+    }
 
-        pnr
+    #[test]
+    fn requires_modulus11_before_exemption_date() {
+        assert!(validate_pnr("070761-4285").is_ok());
+        assert!(validate_pnr("070761-4286").is_err());
+        assert!(validate_pnr("011007-4286").is_ok());
     }
-}
 
-// Function to ensure a year is generated and return a random PNR
-pub fn get_pnr_for_birth_date(birth_date: NaiveDate) -> String {
-    let mut pool = PNR_POOL.lock().unwrap();
-    pool.get_or_create_pnr(birth_date)
-}
+    #[test]
+    fn allocates_unique_numbers_encoding_birth_date_and_sex() {
+        let mut pool = PnrPool::new(SeedStream::new(1), false);
+        let birth_date = date(1985, 3, 14);
+        let mut seen = std::collections::HashSet::new();
+        while let Ok(pnr) = pool.allocate_pnr(birth_date, 'K') {
+            let decoded = validate_pnr(&pnr).unwrap();
+            assert_eq!(decoded.birth_date, birth_date);
+            assert_eq!(decoded.sex, 'K');
+            assert!(seen.insert(pnr));
+        }
+        assert_eq!(seen.len(), valid_serials(birth_date, 'K', true).len());
+    }
 
-// Function to get parents' PNRs
-pub fn get_parents_pnr(pnr: &str) -> (Option<String>, Option<String>) {
-    let mut pool = PNR_POOL.lock().unwrap();
-    if let Some(person) = pool.persons.get(pnr).cloned() {
-        let mother_pnr = person.mother_pnr.clone().or_else(|| {
-            let mother_birth_year = person.birth_date.year()
-                - rand::thread_rng().gen_range(pool.min_parent_age..=pool.max_parent_age);
-            Some(pool.add_person(mother_birth_year, None, None))
-        });
-        let father_pnr = person.father_pnr.clone().or_else(|| {
-            let father_birth_year = person.birth_date.year()
-                - rand::thread_rng().gen_range(pool.min_parent_age..=pool.max_parent_age);
-            Some(pool.add_person(father_birth_year, None, None))
-        });
-        (mother_pnr, father_pnr)
-    } else {
-        (None, None)
+    #[test]
+    fn allows_non_modulus11_only_after_exemption_date() {
+        let mut pool = PnrPool::new(SeedStream::new(1), true);
+        let mut non_modulus11 = |birth_date| {
+            (0..100).any(|_| {
+                let pnr = pool.allocate_pnr(birth_date, 'M').unwrap();
+                !decode_pnr(&pnr).unwrap().modulus11
+            })
+        };
+        assert!(non_modulus11(date(2010, 5, 1)));
+        assert!(!non_modulus11(date(2000, 5, 1)));
     }
 }
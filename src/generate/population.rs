@@ -0,0 +1,901 @@
+use crate::error::DataGeneratorError;
+use crate::generate::mappings::{CodesInYear, KOM, STATSB};
+use crate::generate::migration::{
+    draw_citizenship, draw_origin_country, Direction, Migration, Origin, DENMARK,
+};
+use crate::generate::municipality::municipality_in;
+use crate::generate::pnr::{PnrPool, CPR_FIRST_YEAR};
+use crate::generate::rng::SeedStream;
+use chrono::{Datelike, NaiveDate};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
+
+const MIN_PARENT_AGE: i32 = 18;
+const MAX_PARENT_AGE: i32 = 50;
+/// Oldest age at which children are drawn as still living with their parents.
+const MAX_HOME_CHILD_AGE: i32 = 21;
+
+/// Probability of dying within a year for children in their first two years.
+const INFANT_MORTALITY: f64 = 0.003;
+/// Share of households that emigrate in a given year.
+const EMIGRATION_PROBABILITY: f64 = 0.005;
+/// Share of households abroad that return to Denmark in a given year.
+const RETURN_PROBABILITY: f64 = 0.1;
+/// Immigrant households arriving in a given year per person in the population.
+const IMMIGRATION_RATE: f64 = 0.004;
+/// Share of households headed by an immigrant, and by an adult descendant of
+/// immigrants.
+const IMMIGRANT_HOUSEHOLD_SHARE: f64 = 0.08;
+const DESCENDANT_HOUSEHOLD_SHARE: f64 = 0.03;
+/// Adults in Denmark by age band in thousands, roughly as in 2020. The first
+/// adult of a household is drawn from it.
+const ADULT_AGE_BANDS: [(i32, i32, u32); 9] = [
+    (18, 19, 140),
+    (20, 29, 780),
+    (30, 39, 690),
+    (40, 49, 760),
+    (50, 59, 800),
+    (60, 69, 670),
+    (70, 79, 580),
+    (80, 89, 230),
+    (90, 100, 46),
+];
+/// Share of couples in a partnership that are registered partners of the
+/// same sex rather than married.
+const REGISTERED_PARTNERSHIP_SHARE: f64 = 0.01;
+
+#[derive(Clone)]
+pub struct Person {
+    pub pnr: String,
+    pub birth_date: NaiveDate,
+    pub gender: char,
+    pub mother_pnr: Option<String>,
+    pub father_pnr: Option<String>,
+    pub civst: &'static str,
+    pub spouse_pnr: Option<String>,
+    /// Unmarried partner living in the same household.
+    pub cohabitant_pnr: Option<String>,
+    /// People with the same household id share an address.
+    pub household_id: u64,
+    /// When the person moved to the address, or was born there.
+    pub moved_in: NaiveDate,
+    /// The municipality of the address, from `mappings/kom.json`.
+    pub kom: i16,
+    pub origin: Origin,
+    /// Country code from `mappings/statsb.json`, Denmark for Danish origin.
+    pub origin_country: i32,
+    pub citizenship: i32,
+}
+
+/// A parent who is either a member of the same household, lives elsewhere
+/// and is only known by birth date, or isn't recorded at all.
+#[derive(Clone, Copy)]
+enum ParentDraft {
+    Member(usize),
+    External(NaiveDate),
+    Unknown,
+}
+
+/// The random part of a person, drawn before any PNRs are allocated.
+struct PersonDraft {
+    birth_date: NaiveDate,
+    gender: char,
+    mother: ParentDraft,
+    father: ParentDraft,
+    civst: &'static str,
+    spouse: Option<usize>,
+    cohabitant: Option<usize>,
+    origin: Origin,
+    origin_country: i32,
+    citizenship: i32,
+}
+
+/// An adult, possibly a spouse, and the children living with them. The
+/// adults come first.
+struct HouseholdDraft {
+    persons: Vec<PersonDraft>,
+    adults: usize,
+    /// When the adults moved to the address.
+    moved_in: NaiveDate,
+    kom: i16,
+}
+
+impl HouseholdDraft {
+    /// Makes the household immigrants from `country` who arrived on
+    /// `arrival`. Members born in Denmark after the arrival are descendants.
+    fn immigrate(&mut self, arrival: NaiveDate, year: i32, country: i32, rng: &mut impl Rng) {
+        self.moved_in = self.moved_in.max(arrival);
+        for person in &mut self.persons {
+            person.origin = if person.birth_date < arrival {
+                Origin::Immigrant
+            } else {
+                Origin::Descendant
+            };
+            person.origin_country = country;
+            let years_in_denmark = year - arrival.max(person.birth_date).year();
+            person.citizenship = draw_citizenship(person.origin, country, years_in_denmark, rng);
+        }
+    }
+}
+
+fn random_date_in_year(year: i32, rng: &mut impl Rng) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, rng.gen_range(1..=12), rng.gen_range(1..=28)).unwrap()
+}
+
+fn random_gender(rng: &mut impl Rng) -> char {
+    if rng.gen_bool(0.5) {
+        'M'
+    } else {
+        'K'
+    }
+}
+
+fn opposite_gender(gender: char) -> char {
+    if gender == 'M' {
+        'K'
+    } else {
+        'M'
+    }
+}
+
+fn draw_civst(age: i32, rng: &mut impl Rng) -> &'static str {
+    let married_probability = match age {
+        0..=17 => 0.0,  // No spouse for minors
+        18..=25 => 0.1, // Low probability for young adults
+        26..=35 => 0.5, // Higher probability for adults
+        36..=60 => 0.7, // Highest probability for middle-aged
+        _ => 0.6,       // Slightly lower for seniors
+    };
+
+    if rng.gen_bool(married_probability) {
+        return "G";
+    }
+    draw_unmarried_civst(age, rng)
+}
+
+fn draw_unmarried_civst(age: i32, rng: &mut impl Rng) -> &'static str {
+    match age {
+        0..=25 => "U",
+        26..=60 => ["U", "U", "U", "F"].choose(rng).unwrap(),
+        _ => ["U", "F", "E", "E"].choose(rng).unwrap(),
+    }
+}
+
+/// Probability that an unmarried adult of `age` lives with a partner.
+fn cohabitation_probability(age: i32) -> f64 {
+    match age {
+        18..=25 => 0.2,
+        26..=60 => 0.3,
+        _ => 0.1,
+    }
+}
+
+/// Probability that a parent of `gender` living elsewhere isn't recorded for
+/// someone born in `birth_year`, as is common for cohorts born before CPR.
+fn unknown_parent_probability(birth_year: i32, gender: char) -> f64 {
+    match (gender, birth_year) {
+        ('M', ..=1959) => 0.8,
+        ('M', 1960..=1979) => 0.1,
+        ('M', _) => 0.02,
+        (_, ..=1959) => 0.7,
+        (_, 1960..=1979) => 0.05,
+        _ => 0.0,
+    }
+}
+
+fn external_parent(birth_date: NaiveDate, gender: char, rng: &mut impl Rng) -> ParentDraft {
+    if rng.gen_bool(unknown_parent_probability(birth_date.year(), gender)) {
+        return ParentDraft::Unknown;
+    }
+    ParentDraft::External(random_date_in_year(
+        birth_date.year() - rng.gen_range(MIN_PARENT_AGE..=MAX_PARENT_AGE),
+        rng,
+    ))
+}
+
+fn draft_adult(
+    birth_date: NaiveDate,
+    gender: char,
+    civst: &'static str,
+    rng: &mut impl Rng,
+) -> PersonDraft {
+    PersonDraft {
+        birth_date,
+        gender,
+        mother: external_parent(birth_date, 'K', rng),
+        father: external_parent(birth_date, 'M', rng),
+        civst,
+        spouse: None,
+        cohabitant: None,
+        origin: Origin::Danish,
+        origin_country: DENMARK,
+        citizenship: DENMARK,
+    }
+}
+
+/// Draws the age of an adult from the Danish age pyramid.
+fn draw_adult_age(rng: &mut impl Rng) -> i32 {
+    let bands = WeightedIndex::new(ADULT_AGE_BANDS.iter().map(|(_, _, weight)| weight)).unwrap();
+    let (min, max, _) = ADULT_AGE_BANDS[bands.sample(rng)];
+    rng.gen_range(min..=max)
+}
+
+fn draft_members(
+    year: i32,
+    municipalities: &CodesInYear<i16>,
+    rng: &mut impl Rng,
+) -> HouseholdDraft {
+    let birth_date = random_date_in_year(year - draw_adult_age(rng), rng);
+    let gender = random_gender(rng);
+    let age = year - birth_date.year();
+    let mut civst = draw_civst(age, rng);
+    if civst == "G" && rng.gen_bool(REGISTERED_PARTNERSHIP_SHARE) {
+        civst = "P";
+    }
+    let mut persons = vec![draft_adult(birth_date, gender, civst, rng)];
+
+    let has_partner = matches!(civst, "G" | "P") || rng.gen_bool(cohabitation_probability(age));
+    if has_partner {
+        let partner_year = (birth_date.year() + rng.gen_range(-5..=5)).min(year - MIN_PARENT_AGE);
+        let partner_birth_date = random_date_in_year(partner_year, rng);
+        let (partner_gender, partner_civst) = match civst {
+            "G" => (opposite_gender(gender), "G"),
+            "P" => (gender, "P"),
+            _ => (
+                opposite_gender(gender),
+                draw_unmarried_civst(year - partner_year, rng),
+            ),
+        };
+        let mut partner = draft_adult(partner_birth_date, partner_gender, partner_civst, rng);
+        // Spouses and registered partners both point to each other through
+        // AEGTE_ID, cohabitants only through E_FAELLE_ID
+        if matches!(civst, "G" | "P") {
+            partner.spouse = Some(0);
+            persons[0].spouse = Some(1);
+        } else {
+            partner.cohabitant = Some(0);
+            persons[0].cohabitant = Some(1);
+        }
+        persons.push(partner);
+    }
+    let adults = persons.len();
+    // The adults moved in together once they were all of age
+    let youngest_birth_year = persons.iter().map(|p| p.birth_date.year()).max().unwrap();
+    let moved_in = random_date_in_year(
+        rng.gen_range((youngest_birth_year + MIN_PARENT_AGE).min(year)..=year),
+        rng,
+    );
+    let kom = *municipalities.sample(rng);
+
+    // Children are young enough to live at home and born while every adult
+    // in the household was of parental age
+    let ages = persons.iter().map(|p| year - p.birth_date.year());
+    let min_child_age = (ages.clone().max().unwrap() - MAX_PARENT_AGE).max(0);
+    let max_child_age = (ages.min().unwrap() - MIN_PARENT_AGE).min(MAX_HOME_CHILD_AGE);
+    if min_child_age > max_child_age {
+        return HouseholdDraft {
+            persons,
+            adults,
+            moved_in,
+            kom,
+        };
+    }
+
+    let no_children = if adults == 2 {
+        *[0, 0, 1, 1, 2, 2, 3].choose(rng).unwrap()
+    } else {
+        *[0, 0, 0, 0, 1, 1, 2].choose(rng).unwrap()
+    };
+    // A single adult shares the children with a parent living elsewhere, who
+    // is recorded for all of them or none
+    let oldest_child_birth_year = year - max_child_age;
+    let other_parent = if rng.gen_bool(unknown_parent_probability(
+        oldest_child_birth_year,
+        opposite_gender(gender),
+    )) {
+        ParentDraft::Unknown
+    } else {
+        ParentDraft::External(random_date_in_year(
+            birth_date.year() + rng.gen_range(-5..=5),
+            rng,
+        ))
+    };
+    let parent = |parent_gender: char| {
+        (0..adults)
+            .find(|&i| persons[i].gender == parent_gender)
+            .map_or(other_parent, ParentDraft::Member)
+    };
+    let (mother, father) = (parent('K'), parent('M'));
+
+    for _ in 0..no_children {
+        persons.push(PersonDraft {
+            birth_date: random_date_in_year(
+                year - rng.gen_range(min_child_age..=max_child_age),
+                rng,
+            ),
+            gender: random_gender(rng),
+            mother,
+            father,
+            civst: "U",
+            spouse: None,
+            cohabitant: None,
+            origin: Origin::Danish,
+            origin_country: DENMARK,
+            citizenship: DENMARK,
+        });
+    }
+
+    HouseholdDraft {
+        persons,
+        adults,
+        moved_in,
+        kom,
+    }
+}
+
+fn draft_household(
+    year: i32,
+    countries: &CodesInYear<i32>,
+    municipalities: &CodesInYear<i16>,
+    rng: &mut impl Rng,
+) -> HouseholdDraft {
+    let mut household = draft_members(year, municipalities, rng);
+
+    let roll: f64 = rng.gen();
+    if roll < IMMIGRANT_HOUSEHOLD_SHARE {
+        // The adults arrived together some time after the first turned 18
+        let anchor_birth_year = household.persons[0].birth_date.year();
+        let arrival_year = rng.gen_range(anchor_birth_year + MIN_PARENT_AGE..=year);
+        let arrival = random_date_in_year(arrival_year, rng);
+        household.immigrate(arrival, year, draw_origin_country(countries, rng), rng);
+    } else if roll < IMMIGRANT_HOUSEHOLD_SHARE + DESCENDANT_HOUSEHOLD_SHARE {
+        // An adult descendant whose partner and children are of Danish origin
+        let country = draw_origin_country(countries, rng);
+        let anchor = &mut household.persons[0];
+        anchor.origin = Origin::Descendant;
+        anchor.origin_country = country;
+        anchor.citizenship = draw_citizenship(Origin::Descendant, country, 0, rng);
+    }
+
+    household
+}
+
+/// Draws a household arriving in Denmark during `year`, together with the
+/// arrival date.
+fn draft_immigrant_household(
+    year: i32,
+    countries: &CodesInYear<i32>,
+    municipalities: &CodesInYear<i16>,
+    rng: &mut impl Rng,
+) -> (HouseholdDraft, NaiveDate) {
+    let mut household = draft_members(year, municipalities, rng);
+    let latest_birth_date = household
+        .persons
+        .iter()
+        .map(|p| p.birth_date)
+        .max()
+        .unwrap();
+    let arrival = random_date_in_year(year, rng).max(latest_birth_date.succ_opt().unwrap());
+    household.immigrate(arrival, year, draw_origin_country(countries, rng), rng);
+    (household, arrival)
+}
+
+/// Probability of dying within a year at `age`: a flat risk for infants
+/// followed by a Gompertz curve, with women living a few years longer.
+fn death_probability(age: i32, gender: char) -> f64 {
+    if age <= 1 {
+        return INFANT_MORTALITY;
+    }
+    let (baseline, growth) = if gender == 'M' {
+        (0.00006, 0.092)
+    } else {
+        (0.00003, 0.095)
+    };
+    (baseline * (growth * age as f64).exp()).min(1.0)
+}
+
+/// Probability that a woman of `age` gives birth within a year.
+fn birth_probability(age: i32) -> f64 {
+    match age {
+        18..=24 => 0.04,
+        25..=34 => 0.10,
+        35..=39 => 0.05,
+        40..=MAX_PARENT_AGE => 0.01,
+        _ => 0.0,
+    }
+}
+
+/// What happens to a person between the end of one year and the next.
+struct Transition {
+    death_date: Option<NaiveDate>,
+    birth: Option<(NaiveDate, char)>,
+}
+
+/// Looks up or allocates the PNR of a parent. A parent living elsewhere is
+/// also left unknown when born before the CPR range.
+fn allocate_parent(
+    pool: &mut PnrPool,
+    household_pnrs: &[String],
+    parent: ParentDraft,
+    gender: char,
+) -> Result<Option<String>, DataGeneratorError> {
+    match parent {
+        ParentDraft::Member(i) => Ok(Some(household_pnrs[i].clone())),
+        ParentDraft::Unknown => Ok(None),
+        ParentDraft::External(birth_date) if birth_date.year() < CPR_FIRST_YEAR => Ok(None),
+        ParentDraft::External(birth_date) => pool.allocate_pnr(birth_date, gender).map(Some),
+    }
+}
+
+/// Allocates PNRs for a drafted household, linking its members to each other.
+fn allocate_household(
+    pool: &mut PnrPool,
+    household: &HouseholdDraft,
+    household_id: u64,
+) -> Result<Vec<Person>, DataGeneratorError> {
+    let pnrs = household
+        .persons
+        .iter()
+        .map(|draft| pool.allocate_pnr(draft.birth_date, draft.gender))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut persons = Vec::with_capacity(pnrs.len());
+    for (draft, pnr) in household.persons.iter().zip(&pnrs) {
+        persons.push(Person {
+            pnr: pnr.clone(),
+            birth_date: draft.birth_date,
+            gender: draft.gender,
+            mother_pnr: allocate_parent(pool, &pnrs, draft.mother, 'K')?,
+            father_pnr: allocate_parent(pool, &pnrs, draft.father, 'M')?,
+            civst: draft.civst,
+            spouse_pnr: draft.spouse.map(|i| pnrs[i].clone()),
+            cohabitant_pnr: draft.cohabitant.map(|i| pnrs[i].clone()),
+            household_id,
+            moved_in: household.moved_in.max(draft.birth_date),
+            kom: household.kom,
+            origin: draft.origin,
+            origin_country: draft.origin_country,
+            citizenship: draft.citizenship,
+        });
+    }
+    Ok(persons)
+}
+
+/// A random date in `year` on which a household moves, or `None` if it
+/// doesn't, drawn from the household's own stream.
+fn household_move(
+    seed: &SeedStream,
+    household_id: u64,
+    year: i32,
+    probability: f64,
+) -> Option<NaiveDate> {
+    let mut rng = seed.rng(household_id);
+    rng.gen_bool(probability)
+        .then(|| random_date_in_year(year, &mut rng))
+}
+
+/// The people living in Denmark at the end of a given year.
+///
+/// A population is built once per year before any register is generated, and
+/// every register draws its PNRs from it so that the registers join on PNR.
+/// People are drawn household by household, so spouses and children living
+/// at home are part of the population too.
+pub struct Population {
+    year: i32,
+    persons: Vec<Person>,
+    /// Death dates of the people who died during the year, by PNR.
+    deaths: BTreeMap<String, NaiveDate>,
+    /// People who have emigrated and may return later.
+    abroad: Vec<Person>,
+    /// Moves into and out of Denmark during the year, by date.
+    migrations: Vec<Migration>,
+    next_household_id: u64,
+}
+
+impl Population {
+    pub fn generate(
+        year: i32,
+        size: usize,
+        pool: &mut PnrPool,
+        seed: SeedStream,
+    ) -> Result<Self, DataGeneratorError> {
+        let countries = STATSB.in_year(year)?;
+        let municipalities = KOM.in_year(year)?;
+        // Every household has at least one person, so `size` drafts are enough
+        let households = seed.derive(year.to_string()).par_rows(size, |rng, _| {
+            draft_household(year, &countries, &municipalities, rng)
+        });
+
+        // PNRs are allocated in row order so they don't depend on thread scheduling
+        let mut persons = Vec::with_capacity(size);
+        for (household_id, mut household) in households.into_iter().enumerate() {
+            let remaining = size - persons.len();
+            if remaining == 0 {
+                break;
+            }
+            // Children are left out of the last household to hit `size`, but
+            // couples are never split
+            if household.adults > remaining {
+                continue;
+            }
+            household.persons.truncate(remaining);
+            persons.extend(allocate_household(pool, &household, household_id as u64)?);
+        }
+
+        Ok(Population {
+            year,
+            persons,
+            deaths: BTreeMap::new(),
+            abroad: Vec::new(),
+            migrations: Vec::new(),
+            next_household_id: size as u64,
+        })
+    }
+
+    /// Moves the population forward to the end of the next year.
+    ///
+    /// Everyone who neither dies nor emigrates stays with the same PNR,
+    /// relatives and address, and so ages by one year. Households emigrate
+    /// and return together, returning households settle at a new address,
+    /// new immigrant households arrive, and more of them if that's
+    /// needed for the population to keep its size, and the surviving spouse of
+    /// someone who dies is widowed. Newborns get a mother from the population
+    /// and her male partner, or otherwise a random man of parental age, as
+    /// father, and are of Danish origin unless neither parent is.
+    pub fn advance(
+        &self,
+        pool: &mut PnrPool,
+        seed: SeedStream,
+    ) -> Result<Self, DataGeneratorError> {
+        let year = self.year + 1;
+        let seed = seed.derive(year.to_string());
+        let countries = STATSB.in_year(year)?;
+        let municipalities = KOM.in_year(year)?;
+        let transitions = seed.par_rows(self.persons.len(), |rng, i| {
+            let person = &self.persons[i];
+            let age = year - person.birth_date.year();
+            let death_date = rng
+                .gen_bool(death_probability(age, person.gender))
+                .then(|| random_date_in_year(year, rng));
+            let gives_birth = death_date.is_none()
+                && person.gender == 'K'
+                && rng.gen_bool(birth_probability(age));
+            Transition {
+                death_date,
+                birth: gives_birth.then(|| (random_date_in_year(year, rng), random_gender(rng))),
+            }
+        });
+        let emigration = seed.derive("emigration");
+        let emigration_dates: Vec<Option<NaiveDate>> = self
+            .persons
+            .iter()
+            .map(|person| {
+                household_move(
+                    &emigration,
+                    person.household_id,
+                    year,
+                    EMIGRATION_PROBABILITY,
+                )
+            })
+            .collect();
+        let stays: Vec<bool> = transitions
+            .iter()
+            .zip(&emigration_dates)
+            .map(|(transition, emigration_date)| {
+                transition.death_date.is_none() && emigration_date.is_none()
+            })
+            .collect();
+
+        let deaths: BTreeMap<String, NaiveDate> = self
+            .persons
+            .iter()
+            .zip(&transitions)
+            .filter_map(|(person, transition)| Some((person.pnr.clone(), transition.death_date?)))
+            .collect();
+        let people: HashMap<&str, &Person> = self
+            .persons
+            .iter()
+            .map(|person| (person.pnr.as_str(), person))
+            .collect();
+        let fathers: Vec<&Person> = self
+            .persons
+            .iter()
+            .zip(&stays)
+            .filter(|(person, &stays)| {
+                let age = year - person.birth_date.year();
+                stays
+                    && person.gender == 'M'
+                    && (MIN_PARENT_AGE..=MAX_PARENT_AGE + 10).contains(&age)
+            })
+            .map(|(person, _)| person)
+            .collect();
+        let mut rng = seed.derive("fathers").rng(0);
+
+        let mut persons = Vec::with_capacity(self.persons.len());
+        let mut newborns = Vec::new();
+        let mut abroad = Vec::new();
+        let mut migrations = Vec::new();
+        for (i, person) in self.persons.iter().enumerate() {
+            let transition = &transitions[i];
+            if transition.death_date.is_some() {
+                continue;
+            }
+            let mut survivor = person.clone();
+            survivor.kom = municipality_in(person.kom, year);
+            if survivor
+                .spouse_pnr
+                .as_deref()
+                .is_some_and(|pnr| deaths.contains_key(pnr))
+            {
+                survivor.civst = if survivor.civst == "P" { "L" } else { "E" };
+                survivor.spouse_pnr = None;
+            }
+            if survivor
+                .cohabitant_pnr
+                .as_deref()
+                .is_some_and(|pnr| deaths.contains_key(pnr))
+            {
+                survivor.cohabitant_pnr = None;
+            }
+            if let Some(date) = emigration_dates[i] {
+                migrations.push(Migration {
+                    pnr: survivor.pnr.clone(),
+                    direction: Direction::Emigration,
+                    date,
+                });
+                abroad.push(survivor);
+                continue;
+            }
+            persons.push(survivor);
+
+            // Newborns are allocated in row order so they don't depend on thread scheduling
+            if let Some((birth_date, gender)) = transition.birth {
+                let partner = person
+                    .spouse_pnr
+                    .as_ref()
+                    .or(person.cohabitant_pnr.as_ref());
+                let father = match partner.and_then(|pnr| people.get(pnr.as_str())) {
+                    Some(partner) if partner.gender == 'M' => Some(*partner),
+                    // A father outside the household isn't always recorded
+                    _ if rng.gen_bool(unknown_parent_probability(birth_date.year(), 'M')) => None,
+                    _ => fathers.choose(&mut rng).copied(),
+                };
+                let parents = [Some(person), father];
+                let (origin, origin_country) = if parents
+                    .iter()
+                    .flatten()
+                    .any(|parent| parent.origin == Origin::Danish)
+                {
+                    (Origin::Danish, DENMARK)
+                } else {
+                    (Origin::Descendant, person.origin_country)
+                };
+                let citizenship = if parents
+                    .iter()
+                    .flatten()
+                    .any(|parent| parent.citizenship == DENMARK)
+                {
+                    DENMARK
+                } else {
+                    person.citizenship
+                };
+                newborns.push(Person {
+                    pnr: pool.allocate_pnr(birth_date, gender)?,
+                    birth_date,
+                    gender,
+                    mother_pnr: Some(person.pnr.clone()),
+                    father_pnr: father.map(|father| father.pnr.clone()),
+                    civst: "U",
+                    spouse_pnr: None,
+                    cohabitant_pnr: None,
+                    household_id: person.household_id,
+                    moved_in: birth_date,
+                    kom: municipality_in(person.kom, year),
+                    origin,
+                    origin_country,
+                    citizenship,
+                });
+            }
+        }
+        persons.extend(newborns);
+
+        // Households abroad return together
+        let returns = seed.derive("return");
+        let settlements = seed.derive("settlement");
+        let mut still_abroad = Vec::new();
+        for person in &self.abroad {
+            match household_move(&returns, person.household_id, year, RETURN_PROBABILITY) {
+                Some(date) => {
+                    migrations.push(Migration {
+                        pnr: person.pnr.clone(),
+                        direction: Direction::Immigration,
+                        date,
+                    });
+                    persons.push(Person {
+                        moved_in: date,
+                        kom: *municipalities.sample(&mut settlements.rng(person.household_id)),
+                        ..person.clone()
+                    });
+                }
+                None => still_abroad.push(person.clone()),
+            }
+        }
+        still_abroad.extend(abroad);
+
+        let no_immigrant_households =
+            (self.persons.len() as f64 * IMMIGRATION_RATE).round() as usize;
+        let mut immigrant_households = seed
+            .derive("immigration")
+            .par_rows(no_immigrant_households, |rng, _| {
+                draft_immigrant_household(year, &countries, &municipalities, rng)
+            });
+        // Immigration makes up for the people who died or left beyond that
+        let mut arriving: usize = immigrant_households
+            .iter()
+            .map(|(household, _)| household.persons.len())
+            .sum();
+        let replacements = seed.derive("replacement");
+        while persons.len() + arriving < self.persons.len() {
+            let mut rng = replacements.rng(immigrant_households.len() as u64);
+            let household = draft_immigrant_household(year, &countries, &municipalities, &mut rng);
+            arriving += household.0.persons.len();
+            immigrant_households.push(household);
+        }
+        let mut next_household_id = self.next_household_id;
+        for (household, arrival) in immigrant_households {
+            for person in allocate_household(pool, &household, next_household_id)? {
+                migrations.push(Migration {
+                    pnr: person.pnr.clone(),
+                    direction: Direction::Immigration,
+                    date: arrival,
+                });
+                persons.push(person);
+            }
+            next_household_id += 1;
+        }
+        migrations.sort_by_key(|migration| migration.date);
+
+        Ok(Population {
+            year,
+            persons,
+            deaths,
+            abroad: still_abroad,
+            migrations,
+            next_household_id,
+        })
+    }
+
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    pub fn persons(&self) -> &[Person] {
+        &self.persons
+    }
+
+    pub fn deaths(&self) -> &BTreeMap<String, NaiveDate> {
+        &self.deaths
+    }
+
+    pub fn death_date(&self, pnr: &str) -> Option<NaiveDate> {
+        self.deaths.get(pnr).copied()
+    }
+
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /// The people who emigrated during the year and are abroad at its end.
+    pub fn emigrants(&self) -> impl Iterator<Item = &str> {
+        self.migrations
+            .iter()
+            .filter(|migration| migration.direction == Direction::Emigration)
+            .map(|migration| migration.pnr.as_str())
+    }
+
+    /// Draws `n` PNRs from the population, without replacement as long as the
+    /// population is large enough. With `include_deceased`, the people who
+    /// died during the year can be drawn too.
+    pub fn sample_pnrs(&self, n: usize, include_deceased: bool, seed: SeedStream) -> Vec<String> {
+        let mut candidates: Vec<&str> = self.persons.iter().map(|p| p.pnr.as_str()).collect();
+        if include_deceased {
+            candidates.extend(self.deaths.keys().map(String::as_str));
+        }
+
+        let mut rng = seed.rng(0);
+        if n <= candidates.len() {
+            candidates
+                .choose_multiple(&mut rng, n)
+                .map(|pnr| pnr.to_string())
+                .collect()
+        } else {
+            (0..n)
+                .map(|_| candidates.choose(&mut rng).unwrap().to_string())
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Ten years of a population of 2000, starting in 2010.
+    fn populations() -> Vec<Population> {
+        let seed = SeedStream::new(3);
+        let mut pool = PnrPool::new(seed.derive("pnr"), false);
+        let mut populations = vec![Population::generate(2010, 2000, &mut pool, seed).unwrap()];
+        for _ in 1..10 {
+            let next = populations
+                .last()
+                .unwrap()
+                .advance(&mut pool, seed)
+                .unwrap();
+            populations.push(next);
+        }
+        populations
+    }
+
+    #[test]
+    fn spouses_and_cohabitants_point_at_each_other() {
+        for population in populations() {
+            let people: HashMap<&str, &Person> = population
+                .persons()
+                .iter()
+                .map(|person| (person.pnr.as_str(), person))
+                .collect();
+            for person in population.persons() {
+                if let Some(spouse) = &person.spouse_pnr {
+                    let spouse = people[spouse.as_str()];
+                    assert_eq!(spouse.spouse_pnr.as_deref(), Some(person.pnr.as_str()));
+                    assert_eq!(spouse.civst, person.civst);
+                    assert!(["G", "P"].contains(&person.civst));
+                }
+                if let Some(cohabitant) = &person.cohabitant_pnr {
+                    let cohabitant = people[cohabitant.as_str()];
+                    assert_eq!(
+                        cohabitant.cohabitant_pnr.as_deref(),
+                        Some(person.pnr.as_str())
+                    );
+                    assert_eq!(cohabitant.household_id, person.household_id);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nobody_is_present_after_dying_or_emigrating() {
+        let populations = populations();
+        let mut gone: HashSet<String> = HashSet::new();
+        let mut emigrated = 0;
+        for population in &populations[1..] {
+            let present: HashSet<&str> = population
+                .persons()
+                .iter()
+                .map(|person| person.pnr.as_str())
+                .collect();
+            // Returning emigrants are back in the population
+            for migration in population.migrations() {
+                if migration.direction == Direction::Immigration {
+                    gone.remove(&migration.pnr);
+                }
+            }
+            for pnr in population.deaths().keys() {
+                assert!(!present.contains(pnr.as_str()), "{} died", pnr);
+            }
+            for pnr in population.emigrants() {
+                assert!(!present.contains(pnr), "{} emigrated", pnr);
+                emigrated += 1;
+            }
+            for pnr in &gone {
+                assert!(!present.contains(pnr.as_str()), "{} is gone", pnr);
+            }
+            gone.extend(population.deaths().keys().cloned());
+            gone.extend(population.emigrants().map(str::to_string));
+        }
+        assert!(emigrated > 0);
+    }
+}
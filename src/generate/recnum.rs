@@ -1,91 +1,91 @@
-use chrono::NaiveDate;
-use once_cell::sync::Lazy;
-use rand::seq::IteratorRandom;
-use rand::Rng;
-use std::collections::{HashMap, HashSet};
-use std::sync::Mutex;
+use crate::generate::rng::SeedStream;
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
 
-static RECNUM_POOL: Lazy<Mutex<RecnumPool>> = Lazy::new(|| Mutex::new(RecnumPool::new()));
-
-struct Contact {
-    recnum: String,
-    pnr: String,
-    contact_date: NaiveDate,
+/// A hospital contact, identified by its RECNUM or DW_EK_KONTAKT.
+#[derive(Clone)]
+pub struct Contact {
+    pub pnr: String,
+    pub key: String,
 }
 
-struct RecnumPool {
-    contacts: HashMap<String, Contact>,
-    pnr_to_recnums: HashMap<String, HashSet<String>>,
+/// Hands out RECNUMs and keeps track of the contacts each register has
+/// generated, so that registers linking to them reuse their keys.
+pub struct RecnumPool {
+    /// Contacts by register and year, in the order they were added.
+    contacts: HashMap<(&'static str, i32), Vec<Contact>>,
     next_recnum: u64,
 }
 
+impl Default for RecnumPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RecnumPool {
-    fn new() -> Self {
+    pub fn new() -> Self {
         RecnumPool {
             contacts: HashMap::new(),
-            pnr_to_recnums: HashMap::new(),
             next_recnum: 1,
         }
     }
 
-    fn generate_recnum(&mut self) -> String {
+    /// A key no contact or course of care has been given before.
+    pub fn generate_recnum(&mut self) -> String {
         let recnum = format!("{:020}", self.next_recnum);
         self.next_recnum += 1;
         recnum
     }
 
-    fn add_contact(&mut self, pnr: &str, year: i32) -> String {
-        let mut rng = rand::thread_rng();
-        let contact_date =
-            NaiveDate::from_ymd_opt(year, rng.gen_range(1..13), rng.gen_range(1..29)).unwrap();
-
+    /// Registers a new contact of the person in a register-year and returns
+    /// its RECNUM.
+    pub fn add_contact(&mut self, register: &'static str, year: i32, pnr: &str) -> String {
         let recnum = self.generate_recnum();
-
-        let contact = Contact {
-            recnum: recnum.clone(),
-            pnr: pnr.to_string(),
-            contact_date,
-        };
-
-        self.contacts.insert(recnum.clone(), contact);
-        self.pnr_to_recnums
-            .entry(pnr.to_string())
-            .or_insert_with(HashSet::new)
-            .insert(recnum.clone());
-
+        self.contacts
+            .entry((register, year))
+            .or_default()
+            .push(Contact {
+                pnr: pnr.to_string(),
+                key: recnum.clone(),
+            });
         recnum
     }
 
-    fn get_random_recnum_for_pnr(&self, pnr: &str) -> Option<String> {
-        self.pnr_to_recnums
-            .get(pnr)
-            .and_then(|recnums| recnums.iter().choose(&mut rand::thread_rng()).cloned())
+    /// The contacts of a register-year.
+    pub fn contacts(&self, register: &'static str, year: i32) -> &[Contact] {
+        self.contacts
+            .get(&(register, year))
+            .map_or(&[], Vec::as_slice)
     }
 
-    fn get_or_create_recnum_for_pnr(&mut self, pnr: &str, year: i32) -> String {
-        if let Some(recnum) = self.get_random_recnum_for_pnr(pnr) {
-            recnum
-        } else {
-            self.add_contact(pnr, year)
+    /// Draws `n` of the contacts the registers generated in `year`, every
+    /// contact once before any is drawn again.
+    pub fn sample_contacts(
+        &self,
+        registers: &[&'static str],
+        year: i32,
+        n: usize,
+        seed: SeedStream,
+    ) -> Vec<Contact> {
+        let candidates: Vec<&Contact> = registers
+            .iter()
+            .flat_map(|register| self.contacts(register, year))
+            .collect();
+        if candidates.is_empty() {
+            return Vec::new();
         }
-    }
-}
-
-pub fn get_recnum_for_pnr(pnr: &str, year: i32) -> String {
-    let mut pool = RECNUM_POOL.lock().unwrap();
-    pool.get_or_create_recnum_for_pnr(pnr, year)
-}
 
-pub fn get_random_recnum() -> String {
-    let pool = RECNUM_POOL.lock().unwrap();
-    pool.contacts
-        .keys()
-        .choose(&mut rand::thread_rng())
-        .cloned()
-        .unwrap()
-}
-
-pub fn generate_recnum() -> String {
-    let mut pool = RECNUM_POOL.lock().unwrap();
-    pool.generate_recnum()
+        let mut rng = seed.rng(0);
+        let mut sampled: Vec<Contact> = Vec::with_capacity(n);
+        while sampled.len() < n {
+            let remaining = n - sampled.len();
+            sampled.extend(
+                candidates
+                    .choose_multiple(&mut rng, remaining.min(candidates.len()))
+                    .map(|contact| (*contact).clone()),
+            );
+        }
+        sampled
+    }
 }
@@ -0,0 +1,52 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+
+/// A node in a tree of reproducible random streams.
+///
+/// Every register, year, column and row derives its own stream from the run
+/// seed, so the values never depend on how work is split across threads.
+#[derive(Clone, Copy, Debug)]
+pub struct SeedStream(u64);
+
+impl SeedStream {
+    pub fn new(seed: u64) -> Self {
+        SeedStream(seed)
+    }
+
+    /// Derives an independent child stream identified by `key`.
+    pub fn derive(&self, key: impl AsRef<[u8]>) -> Self {
+        // FNV-1a over the key, mixed with the parent seed
+        let hash = key
+            .as_ref()
+            .iter()
+            .fold(0xcbf29ce484222325_u64, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+        SeedStream(splitmix64(self.0 ^ splitmix64(hash)))
+    }
+
+    /// The RNG for the item at `index` within this stream.
+    pub fn rng(&self, index: u64) -> ChaCha8Rng {
+        ChaCha8Rng::seed_from_u64(splitmix64(self.0.wrapping_add(index)))
+    }
+
+    /// Maps every row index through `f` in parallel, giving each row its own RNG.
+    pub fn par_rows<T, F>(&self, no_rows: usize, f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(&mut ChaCha8Rng, usize) -> T + Sync + Send,
+    {
+        (0..no_rows)
+            .into_par_iter()
+            .map(|i| f(&mut self.rng(i as u64), i))
+            .collect()
+    }
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
@@ -0,0 +1,294 @@
+use polars::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// A register schema as stored in `{register}.json`.
+#[derive(Deserialize)]
+pub struct Schema {
+    pub columns: Vec<ColumnSpec>,
+}
+
+impl Schema {
+    /// The columns delivered in `year`, in schema order.
+    pub fn columns_for(&self, year: i32) -> impl Iterator<Item = &ColumnSpec> {
+        self.columns
+            .iter()
+            .filter(move |column| column.is_valid_in(year))
+    }
+
+    /// Checks that a column listed more than once, e.g. with a different
+    /// mapping per period, never applies twice in the same year.
+    pub fn check_periods(&self) -> Result<(), String> {
+        for (i, a) in self.columns.iter().enumerate() {
+            for b in self.columns[i + 1..].iter().filter(|b| b.name == a.name) {
+                let from = a.valid_from.max(b.valid_from).unwrap_or(i32::MIN);
+                let to = match (a.valid_to, b.valid_to) {
+                    (Some(a), Some(b)) => a.min(b),
+                    (to, None) | (None, to) => to.unwrap_or(i32::MAX),
+                };
+                if from <= to {
+                    return Err(format!(
+                        "column {} is listed more than once for the same years",
+                        a.name
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One column of a register schema.
+///
+/// A column whose code list changed over time is listed once per period,
+/// each with its own `valid_from`/`valid_to` and generator.
+#[derive(Deserialize)]
+pub struct ColumnSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// What the column holds, used as the variable label in formats that
+    /// have them.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Produces the column from the schema alone instead of the register's
+    /// own code, so new variables don't need changes to the generator.
+    #[serde(default)]
+    pub generator: Option<ColumnGenerator>,
+    /// Share of rows left empty, on top of any the generator leaves empty.
+    #[serde(default)]
+    pub null_rate: Option<NullRate>,
+    /// The first year the column is delivered, if not always.
+    #[serde(default)]
+    pub valid_from: Option<i32>,
+    /// The last year the column is delivered, if it has been discontinued.
+    #[serde(default)]
+    pub valid_to: Option<i32>,
+    /// The mapping file with the labels of the column's codes, for columns
+    /// the register's own code fills with codes.
+    #[serde(default)]
+    pub labels: Option<String>,
+}
+
+impl ColumnSpec {
+    pub fn is_valid_in(&self, year: i32) -> bool {
+        self.valid_from.is_none_or(|from| year >= from) && self.valid_to.is_none_or(|to| year <= to)
+    }
+
+    /// The mapping file labelling the column's codes, which for mapping
+    /// generators is the mapping itself.
+    pub fn labels_file(&self) -> Option<&str> {
+        match (&self.labels, &self.generator) {
+            (Some(file), _) | (None, Some(ColumnGenerator::Mapping { file })) => Some(file),
+            _ => None,
+        }
+    }
+}
+
+/// The share of rows of a column that are missing, between 0 and 1.
+///
+/// Either a number, or rates by a characteristic of the person behind the
+/// row, e.g. `{ "by": "birth_year", "bands": [{ "to": 1959, "rate": 0.8 }],
+/// "otherwise": 0.02 }`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum NullRate {
+    Fixed(f64),
+    Conditional {
+        by: PersonAttribute,
+        bands: Vec<NullRateBand>,
+        /// The rate for people outside every band.
+        #[serde(default)]
+        otherwise: f64,
+    },
+}
+
+impl NullRate {
+    /// The rate for a person with the given value of the attribute, or
+    /// without one if it can't be determined.
+    pub fn rate(&self, value: Option<i32>) -> f64 {
+        match self {
+            NullRate::Fixed(rate) => *rate,
+            NullRate::Conditional {
+                bands, otherwise, ..
+            } => value
+                .and_then(|value| bands.iter().find(|band| band.contains(value)))
+                .map_or(*otherwise, |band| band.rate),
+        }
+    }
+
+    /// Every rate the model can give.
+    pub fn rates(&self) -> Vec<f64> {
+        match self {
+            NullRate::Fixed(rate) => vec![*rate],
+            NullRate::Conditional {
+                bands, otherwise, ..
+            } => bands
+                .iter()
+                .map(|band| band.rate)
+                .chain([*otherwise])
+                .collect(),
+        }
+    }
+}
+
+/// A characteristic of the person behind a row that missingness can depend on.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PersonAttribute {
+    /// Age at the end of the register year.
+    Age,
+    BirthYear,
+}
+
+/// A null rate for the people whose attribute lies in `from..=to`.
+#[derive(Deserialize)]
+pub struct NullRateBand {
+    #[serde(default)]
+    pub from: Option<i32>,
+    #[serde(default)]
+    pub to: Option<i32>,
+    pub rate: f64,
+}
+
+impl NullRateBand {
+    fn contains(&self, value: i32) -> bool {
+        self.from.is_none_or(|from| value >= from) && self.to.is_none_or(|to| value <= to)
+    }
+}
+
+/// How a column declared in a schema is generated.
+///
+/// Written in the schema as an object tagged by `kind`, e.g.
+/// `{ "kind": "int_range", "min": 0, "max": 99 }`.
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ColumnGenerator {
+    /// A code from the keys of a mapping file that is valid in the register
+    /// year, drawn by the codes' `weight`s. Codes without one weigh 1, so a
+    /// mapping without weights is drawn uniformly.
+    Mapping { file: String },
+    /// An integer drawn uniformly from `min..=max`.
+    IntRange { min: i64, max: i64 },
+    /// One of `values`, drawn by the matching `weights` or uniformly.
+    Categories {
+        values: Vec<Value>,
+        #[serde(default)]
+        weights: Option<Vec<f64>>,
+    },
+    /// A date within the register year, no later than the person's death.
+    DateInYear,
+}
+
+/// Parses a column type from a schema file, e.g. `"Int8"` or `"Date"`.
+pub fn parse_dtype(name: &str) -> Option<DataType> {
+    let dtype = match name {
+        "Utf8" | "String" => DataType::String,
+        "Boolean" => DataType::Boolean,
+        "Int8" => DataType::Int8,
+        "Int16" => DataType::Int16,
+        "Int32" => DataType::Int32,
+        "Int64" => DataType::Int64,
+        "UInt8" => DataType::UInt8,
+        "UInt32" => DataType::UInt32,
+        "UInt64" => DataType::UInt64,
+        "Float32" => DataType::Float32,
+        "Float64" => DataType::Float64,
+        "Date" => DataType::Date,
+        "Datetime" => DataType::Datetime(TimeUnit::Milliseconds, None),
+        "Time" => DataType::Time,
+        _ => return None,
+    };
+    Some(dtype)
+}
+
+/// Casts a generated column to the type declared in the schema.
+///
+/// Only numbers are converted between widths, and dates to datetimes and
+/// back. Anything else, such as a string column declared as a date, or
+/// values that don't fit the declared width, is an error describing why.
+pub fn cast_to_schema(series: &Series, dtype: &DataType) -> Result<Series, String> {
+    let found = series.dtype();
+    if found == dtype {
+        return Ok(series.clone());
+    }
+
+    let convertible = (found.is_numeric() && dtype.is_numeric())
+        || matches!(
+            (found, dtype),
+            (DataType::Date, DataType::Datetime(..)) | (DataType::Datetime(..), DataType::Date)
+        );
+    if !convertible {
+        return Err(format!("the generator produces {}", found));
+    }
+
+    series
+        .strict_cast(dtype)
+        .map_err(|_| format!("the generated {} values don't fit", found))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(json: &str) -> Schema {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn accepts_consecutive_periods_of_a_column() {
+        let schema = schema(
+            r#"{ "columns": [
+                { "name": "SOCIO", "type": "Int16", "valid_to": 2001 },
+                { "name": "SOCIO", "type": "Int16", "valid_from": 2002, "valid_to": 2013 },
+                { "name": "SOCIO", "type": "Int16", "valid_from": 2014 }
+            ] }"#,
+        );
+        assert!(schema.check_periods().is_ok());
+    }
+
+    #[test]
+    fn rejects_overlapping_periods_of_a_column() {
+        for periods in [
+            (r#""valid_to": 2002"#, r#""valid_from": 2002"#),
+            (r#""valid_from": 2000"#, r#""valid_from": 2010"#),
+            (r#""valid_to": 2000"#, r#""valid_to": 2010"#),
+            (
+                r#""valid_from": 2005, "valid_to": 2006"#,
+                r#""valid_from": 2000"#,
+            ),
+        ] {
+            let schema = schema(&format!(
+                r#"{{ "columns": [
+                    {{ "name": "SOCIO", "type": "Int16", {} }},
+                    {{ "name": "SOCIO", "type": "Int16", {} }}
+                ] }}"#,
+                periods.0, periods.1
+            ));
+            assert!(
+                schema.check_periods().is_err(),
+                "{:?} was accepted",
+                periods
+            );
+        }
+    }
+
+    #[test]
+    fn lists_the_columns_valid_in_a_year() {
+        let schema = schema(
+            r#"{ "columns": [
+                { "name": "PNR", "type": "Utf8" },
+                { "name": "SOCIO", "type": "Int16", "valid_to": 2001 },
+                { "name": "SOCIO02", "type": "Int16", "valid_from": 2002 }
+            ] }"#,
+        );
+        let names = |year| {
+            schema
+                .columns_for(year)
+                .map(|column| column.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(2001), ["PNR", "SOCIO"]);
+        assert_eq!(names(2002), ["PNR", "SOCIO02"]);
+    }
+}
@@ -1,10 +1,12 @@
+use crate::error::DataGeneratorError;
+use crate::generate::assets::read_mapping;
 use crate::generate::mappings::SCD;
 use chrono::NaiveDate;
 use rand::seq::SliceRandom;
 use rand::Rng;
 use serde::de::DeserializeOwned;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 
@@ -14,43 +16,45 @@ pub fn load_json(json_file: &Path) -> Result<Value, Box<dyn std::error::Error>>
     Ok(json)
 }
 
-pub fn load_mapping<K: std::str::FromStr + std::hash::Hash + Eq, V: DeserializeOwned + Clone>(
-    file_path: &str,
-) -> HashMap<K, V> {
-    let file_content =
-        fs::read_to_string(file_path).expect(&format!("Failed to read file: {}", file_path));
-    let json_map: HashMap<String, V> = serde_json::from_str(&file_content)
-        .expect(&format!("Failed to parse JSON from file: {}", file_path));
+/// Loads a mapping file such as `reg.json`. Fails on codes that don't parse
+/// as `K`.
+pub fn load_mapping<K: std::str::FromStr + Ord, V: DeserializeOwned + Clone>(
+    file_name: &str,
+) -> Result<BTreeMap<K, V>, DataGeneratorError> {
+    parse_mapping(file_name, &read_mapping(file_name)?)
+}
+
+/// Parses the contents of the mapping file `file_name`, see [`load_mapping`].
+pub fn parse_mapping<K: std::str::FromStr + Ord, V: DeserializeOwned + Clone>(
+    file_name: &str,
+    file_content: &str,
+) -> Result<BTreeMap<K, V>, DataGeneratorError> {
+    let invalid = |reason: String| DataGeneratorError::InvalidMapping {
+        file: file_name.to_string(),
+        reason,
+    };
+    let json_map: HashMap<String, V> =
+        serde_json::from_str(file_content).map_err(|e| invalid(e.to_string()))?;
 
     json_map
         .into_iter()
-        .filter_map(|(k, v)| K::from_str(&k).ok().map(|parsed_k| (parsed_k, v)))
+        .map(|(k, v)| match K::from_str(&k) {
+            Ok(parsed_k) => Ok((parsed_k, v)),
+            Err(_) => Err(invalid(format!("the code '{}' isn't a valid code", k))),
+        })
         .collect()
 }
 
-pub fn generate_date_for_year(year: i32) -> String {
-    let mut rng = rand::thread_rng();
+pub fn generate_date_for_year(year: i32, rng: &mut impl Rng) -> NaiveDate {
     let month = rng.gen_range(1..13);
     let day = rng.gen_range(1..29);
-    NaiveDate::from_ymd_opt(year, month, day)
-        .unwrap()
-        .format("%Y-%m-%d")
-        .to_string()
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
 }
 
-pub fn get_random_diagnosis() -> String {
-    let mut rng = rand::thread_rng();
-
+pub fn get_random_diagnosis(rng: &mut impl Rng) -> String {
     if rng.gen_bool(0.1) {
         // 10% chance of using SCD mapping
-        return format!(
-            "D{}",
-            SCD.keys()
-                .collect::<Vec<_>>()
-                .choose(&mut rng)
-                .unwrap()
-                .to_string()
-        );
+        return format!("D{}", SCD.sample(rng));
     }
 
     // 90% chance of generating a new code
@@ -59,7 +63,7 @@ pub fn get_random_diagnosis() -> String {
         "S", "T", "U", "V", "W", "X", "Y", "Z",
     ];
 
-    let letter = chapter_letters.choose(&mut rng).unwrap();
+    let letter = chapter_letters.choose(rng).unwrap();
     let first_number: u8 = rng.gen_range(0..=99);
     let second_number: u8 = rng.gen_range(0..=9);
 
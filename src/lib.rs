@@ -0,0 +1,10 @@
+pub mod cli;
+pub mod config;
+pub mod error;
+pub mod generate;
+pub mod read;
+pub mod registers;
+pub mod write;
+
+pub use error::DataGeneratorError;
+pub use generate::pnr::{decode_pnr, validate_pnr, DecodedPnr};
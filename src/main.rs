@@ -1,23 +1,29 @@
-mod cli;
-mod config;
-mod error;
-mod generate;
-mod read;
-mod registers;
-mod write;
-
 use polars::prelude::DataFrame;
+use std::collections::HashMap;
 use std::env;
 use std::path::Path;
 use std::time::Instant;
 
-use config::Config;
-use error::DataGeneratorError;
-use generate::generate_data;
-use read::{read_partitioned_parquet, read_single_parquet_file};
-use write::{write_dataframe_to_multi_parquet, write_dataframe_to_single_parquet};
+use cdef_data_generator::cli;
+use cdef_data_generator::config::Config;
+use cdef_data_generator::error::DataGeneratorError;
+use cdef_data_generator::generate::generate_data;
+use cdef_data_generator::read::{read_partitioned, read_single_file};
+use cdef_data_generator::write::{
+    create_sqlite_database, write_dataframe_to_dta, write_dataframe_to_multi_files,
+    write_dataframe_to_multi_parquet, write_dataframe_to_single_csv, write_dataframe_to_single_ipc,
+    write_dataframe_to_single_parquet, write_dataframe_to_sqlite, write_dataframe_to_xport,
+    OutputFormat, SqliteTable, StataDataset, XportDataset,
+};
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
 
-fn main() -> Result<(), DataGeneratorError> {
+fn run() -> Result<(), DataGeneratorError> {
     let cli = cli::Cli::parse_args()?;
     let config = Config::new(&cli)?;
 
@@ -36,9 +42,9 @@ fn process_input(config: &Config, input_path: &Path) -> Result<(), DataGenerator
     let start_time = Instant::now();
 
     let df = if input_path.is_dir() {
-        read_partitioned_parquet(input_path)?
+        read_partitioned(input_path)?
     } else if input_path.is_file() {
-        read_single_parquet_file(input_path)?
+        read_single_file(input_path)?
     } else {
         return Err(DataGeneratorError::InvalidInput(format!(
             "Input path \"{}\" is neither a file nor a directory",
@@ -48,10 +54,10 @@ fn process_input(config: &Config, input_path: &Path) -> Result<(), DataGenerator
 
     let elapsed = start_time.elapsed().as_secs_f64();
     println!("{:?}", df);
-    println!("Time taken to read from Parquet: {:.3} seconds", elapsed);
+    println!("Time taken to read input: {:.3} seconds", elapsed);
 
     if let Some(output_path) = &config.output {
-        write_output(&df, output_path, config.rows, config.threads)?;
+        write_output(&df, output_path, config)?;
     }
 
     Ok(())
@@ -66,7 +72,8 @@ fn generate_output(config: &Config) -> Result<(), DataGeneratorError> {
         .unwrap_or_else(|| Path::new("output"));
 
     let years = (config.years.0..=config.years.1).collect::<Vec<i32>>();
-    generate_data(&config.registers, config.rows, &years, output_dir)?;
+    println!("Using seed: {}", config.seed);
+    generate_data(config, output_dir)?;
 
     let elapsed = start_time.elapsed().as_secs_f64();
     println!(
@@ -84,8 +91,7 @@ fn generate_output(config: &Config) -> Result<(), DataGeneratorError> {
 fn write_output(
     df: &DataFrame,
     output_path: &Path,
-    rows: usize,
-    threads: usize,
+    config: &Config,
 ) -> Result<(), DataGeneratorError> {
     let is_partitioned = output_path.to_str().unwrap_or("").contains('/');
 
@@ -104,24 +110,82 @@ fn write_output(
 
     let start_time = Instant::now();
 
+    let format = config.format.name();
     if is_partitioned {
         println!(
-            "Output directory for multi-parquet file data: {}",
+            "Output directory for multi-file {} data: {}",
+            format,
             output_path.display()
         );
         let dataset_id = "0";
-        let chunk_size = rows / threads;
-        write_dataframe_to_multi_parquet(df, dataset_id, output_path, chunk_size)?;
+        let chunk_size = config.rows / config.threads;
+        match config.format {
+            OutputFormat::Parquet => {
+                write_dataframe_to_multi_parquet(df, dataset_id, output_path, chunk_size)?;
+            }
+            _ => write_dataframe_to_multi_files(
+                df,
+                dataset_id,
+                output_path,
+                chunk_size,
+                &config.file_extension(),
+                |chunk, path| write_file(chunk, path, config),
+            )?,
+        }
     } else {
         println!(
-            "Output file for single-parquet file data: {}",
+            "Output file for single-file {} data: {}",
+            format,
             output_path.display()
         );
-        write_dataframe_to_single_parquet(&mut df.clone(), output_path)?;
+        write_file(df, output_path, config)?;
     }
 
     let elapsed = start_time.elapsed().as_secs_f64();
-    println!("Time taken to write to Parquet: {:.3} seconds", elapsed);
+    println!("Time taken to write to {}: {:.3} seconds", format, elapsed);
 
     Ok(())
 }
+
+/// Writes a DataFrame to a single file in the configured format.
+fn write_file(df: &DataFrame, file_path: &Path, config: &Config) -> Result<(), DataGeneratorError> {
+    match config.format {
+        OutputFormat::Parquet => write_dataframe_to_single_parquet(&mut df.clone(), file_path),
+        OutputFormat::Csv => write_dataframe_to_single_csv(df, file_path, &config.csv),
+        OutputFormat::Ipc => {
+            write_dataframe_to_single_ipc(&mut df.clone(), file_path, config.ipc_compression)
+        }
+        OutputFormat::Xport => {
+            // Without a register, the dataset is named after the file
+            let dataset = XportDataset {
+                name: file_path
+                    .file_stem()
+                    .map_or("DATA".into(), |stem| stem.to_string_lossy().into_owned()),
+                label: String::new(),
+                column_labels: HashMap::new(),
+            };
+            write_dataframe_to_xport(df, file_path, &dataset, &config.xport)
+        }
+        OutputFormat::Stata => {
+            let dataset = StataDataset {
+                label: String::new(),
+                column_labels: HashMap::new(),
+                value_labels: HashMap::new(),
+            };
+            write_dataframe_to_dta(df, file_path, &dataset)
+        }
+        OutputFormat::Sqlite => {
+            // A new database with one table named after the file
+            create_sqlite_database(file_path, config.overwrite)?;
+            let table = SqliteTable::new(
+                file_path
+                    .file_stem()
+                    .map_or("data".into(), |stem| stem.to_string_lossy().into_owned()),
+                df.get_columns()
+                    .iter()
+                    .map(|column| (column.name().to_string(), column.dtype().clone())),
+            )?;
+            write_dataframe_to_sqlite(df, file_path, &table)
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::error::DataGeneratorError;
 use std::error::Error;
 use std::fs::{self, File};
+use std::io::Read;
 use std::path::Path;
 
 use polars::prelude::*;
@@ -11,25 +12,46 @@ pub fn read_single_parquet_file(file_path: &Path) -> Result<DataFrame, DataGener
     Ok(df)
 }
 
-pub fn read_partitioned_parquet(base_dir: &Path) -> Result<DataFrame, DataGeneratorError> {
+/// The extensions Arrow IPC files are recognised by in partitioned input.
+pub const IPC_EXTENSIONS: &[&str] = &["arrow", "ipc", "feather"];
+
+pub fn read_single_ipc_file(file_path: &Path) -> Result<DataFrame, DataGeneratorError> {
+    let file = File::open(file_path)?;
+    let df = IpcReader::new(file).finish()?;
+    Ok(df)
+}
+
+/// Reads a Parquet or Arrow IPC file, told apart by the magic bytes at its
+/// start rather than its extension.
+pub fn read_single_file(file_path: &Path) -> Result<DataFrame, DataGeneratorError> {
+    let mut magic = [0; 6];
+    let is_ipc = File::open(file_path)?.read_exact(&mut magic).is_ok() && &magic == b"ARROW1";
+    if is_ipc {
+        read_single_ipc_file(file_path)
+    } else {
+        read_single_parquet_file(file_path)
+    }
+}
+
+/// Reads and stacks the Parquet and Arrow IPC files of a directory tree.
+pub fn read_partitioned(base_dir: &Path) -> Result<DataFrame, DataGeneratorError> {
     let mut dataframes: Vec<DataFrame> = Vec::new();
 
-    fn read_parquet_files(
-        path: &Path,
-        dataframes: &mut Vec<DataFrame>,
-    ) -> Result<(), Box<dyn Error>> {
+    fn read_files(path: &Path, dataframes: &mut Vec<DataFrame>) -> Result<(), Box<dyn Error>> {
         if path.is_dir() {
             for entry in fs::read_dir(path)? {
                 let entry = entry?;
                 let path = entry.path();
+                let extension = path.extension().and_then(|s| s.to_str());
                 if path.is_dir() {
                     // Recursively read nested directories
-                    read_parquet_files(&path, dataframes)?;
-                } else if path.is_file()
-                    && path.extension().and_then(|s| s.to_str()) == Some("parquet")
-                {
+                    read_files(&path, dataframes)?;
+                } else if path.is_file() && extension == Some("parquet") {
                     let df = ParquetReader::new(File::open(path)?).finish()?;
                     dataframes.push(df);
+                } else if path.is_file() && extension.is_some_and(|e| IPC_EXTENSIONS.contains(&e)) {
+                    let df = IpcReader::new(File::open(path)?).finish()?;
+                    dataframes.push(df);
                 }
             }
         }
@@ -37,10 +59,10 @@ pub fn read_partitioned_parquet(base_dir: &Path) -> Result<DataFrame, DataGenera
     }
 
     let base_path = Path::new(base_dir);
-    read_parquet_files(base_path, &mut dataframes)?;
+    read_files(base_path, &mut dataframes)?;
 
     // Iteratively vstack DataFrames
-    let mut combined_df = match dataframes.get(0) {
+    let mut combined_df = match dataframes.first() {
         Some(df) => df.clone(),
         None => return Err(DataGeneratorError::Other("No dataframes found".to_string())),
     };
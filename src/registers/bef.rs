@@ -1,195 +1,269 @@
-use crate::generate::mappings::{CIVST, FM_MARK, HUSTYPE, PLADS, REG, STATSB};
-use crate::generate::pnr::{get_parents_pnr, get_pnr_for_birth_date};
+use crate::error::DataGeneratorError;
+use crate::generate::columns::build_frame;
+use crate::generate::context::RegisterContext;
+use crate::generate::family::{family_positions, FamilyPosition};
+use crate::generate::municipality::region_of;
+use crate::generate::pnr::cprtjek;
+use crate::generate::population::{Person, Population};
+use crate::generate::rng::SeedStream;
+use crate::generate::schema::Schema;
+use crate::registers::Register;
 use chrono::{Datelike, NaiveDate};
 use polars::prelude::*;
-use rand::seq::SliceRandom;
-use rand::Rng;
-use rayon::prelude::*;
 
-fn generate_birth_date_and_pnr(year: i32) -> (NaiveDate, String) {
-    let mut rng = rand::thread_rng();
-    let birth_year = rng.gen_range(year - 100..=year);
-    let birth_month = rng.gen_range(1..=12);
-    let birth_day = rng.gen_range(1..=28);
-    let birth_date = NaiveDate::from_ymd_opt(birth_year, birth_month, birth_day).unwrap();
-    let pnr = get_pnr_for_birth_date(birth_date);
-    (birth_date, pnr)
+/// One BEF row, with every column derived from the same person.
+pub struct BefRecord {
+    pnr: String,
+    foed_dag: NaiveDate,
+    alder: i32,
+    koen: char,
+    civst: &'static str,
+    mor_id: Option<String>,
+    far_id: Option<String>,
+    aegte_id: Option<String>,
+    e_faelle_id: Option<String>,
+    familie_id: String,
+    familie_type: i8,
+    antboernf: i8,
+    antboernh: i8,
+    antpersf: i8,
+    antpersh: i8,
+    fm_mark: i8,
+    hustype: i8,
+    plads: i8,
+    reg: i8,
+    kom: i16,
+    statsb: i32,
+    ie_type: &'static str,
+    opr_land: String,
+    bop_vfra: NaiveDate,
+    cprtjek: i8,
 }
 
-pub fn create_bef_series(col_name: &str, no_rows: usize, year: i32) -> Series {
-    let col_name = PlSmallStr::from(col_name);
+fn create_bef_record(person: &Person, family: &FamilyPosition, year: i32) -> BefRecord {
+    BefRecord {
+        pnr: person.pnr.clone(),
+        foed_dag: person.birth_date,
+        alder: year - person.birth_date.year(),
+        koen: person.gender,
+        civst: person.civst,
+        mor_id: person.mother_pnr.clone(),
+        far_id: person.father_pnr.clone(),
+        aegte_id: person.spouse_pnr.clone(),
+        e_faelle_id: family.partner_pnr.clone(),
+        familie_id: family.family_id.clone(),
+        familie_type: family.family_type,
+        antboernf: family.children_in_family,
+        antboernh: family.children_in_household,
+        antpersf: family.persons_in_family,
+        antpersh: family.persons_in_household,
+        fm_mark: family.fm_mark,
+        hustype: family.hustype,
+        plads: family.plads,
+        reg: region_of(person.kom),
+        kom: person.kom,
+        statsb: person.citizenship,
+        ie_type: person.origin.ie_type(),
+        opr_land: person.origin_country.to_string(),
+        bop_vfra: person.moved_in,
+        cprtjek: cprtjek(&person.pnr),
+    }
+}
 
-    match col_name.as_str() {
-        "PNR" | "FOED_DAG" | "ALDER" => {
-            let data: Vec<(NaiveDate, String)> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| generate_birth_date_and_pnr(year))
-                .collect();
+pub struct Bef;
 
-            match col_name.as_str() {
-                "PNR" => Series::new(
-                    col_name,
-                    data.iter()
-                        .map(|(_, pnr)| pnr.clone())
-                        .collect::<Vec<String>>(),
-                ),
-                "FOED_DAG" => Series::new(
-                    col_name,
-                    data.iter()
-                        .map(|(date, _)| date.format("%Y-%m-%d").to_string())
-                        .collect::<Vec<String>>(),
-                ),
-                "ALDER" => Series::new(
-                    col_name,
-                    data.iter()
-                        .map(|(date, _)| year - date.year())
-                        .collect::<Vec<i32>>(),
-                ),
-                _ => unreachable!(),
-            }
-        }
-        "FAR_ID" | "MOR_ID" => {
-            let pnrs = create_bef_series("PNR", no_rows, year);
-            let data: Vec<Option<String>> = pnrs
-                .str()
-                .unwrap()
-                .into_iter()
-                .map(|pnr| {
-                    let (mother, father) = get_parents_pnr(pnr.unwrap());
-                    if col_name.as_str() == "FAR_ID" {
-                        father
-                    } else {
-                        mother
-                    }
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
-        "KOEN" => {
-            let pnrs = create_bef_series("PNR", no_rows, year);
-            let data: Vec<String> = pnrs
-                .str()
-                .unwrap()
-                .into_iter()
-                .map(|pnr| {
-                    let last_digit = pnr.unwrap().chars().last().unwrap().to_digit(10).unwrap();
-                    if last_digit % 2 == 0 { "K" } else { "M" }.to_string()
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
-        "CIVST" => {
-            let ages = create_bef_series("ALDER", no_rows, year);
-            let data: Vec<String> = ages
-                .i32()
-                .unwrap()
-                .into_iter()
-                .map(|age| {
-                    let mut rng = rand::thread_rng();
-                    let civst_key = match age.unwrap() {
-                        0..=17 => "U",
-                        18..=24 => {
-                            if rng.gen_bool(0.8) {
-                                "U"
-                            } else {
-                                "G"
-                            }
-                        }
-                        _ => ["U", "G", "F", "E"].choose(&mut rng).unwrap(),
-                    };
-                    CIVST
-                        .get(civst_key)
-                        .unwrap_or(&civst_key.to_string())
-                        .clone()
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
-        "FM_MARK" => {
-            let keys: Vec<i8> = FM_MARK.keys().cloned().collect();
-            let data: Vec<i8> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| *keys.choose(&mut rand::thread_rng()).unwrap())
-                .collect();
-            Series::new(col_name, data)
-        }
-        "HUSTYPE" => {
-            let keys: Vec<i8> = HUSTYPE.keys().cloned().collect();
-            let data: Vec<i8> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| *keys.choose(&mut rand::thread_rng()).unwrap())
-                .collect();
-            Series::new(col_name, data)
-        }
-        "PLADS" => {
-            let keys: Vec<i8> = PLADS.keys().cloned().collect();
-            let data: Vec<i8> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| *keys.choose(&mut rand::thread_rng()).unwrap())
-                .collect();
-            Series::new(col_name, data)
-        }
-        "REG" => {
-            let keys: Vec<i8> = REG.keys().cloned().collect();
-            let data: Vec<i8> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| *keys.choose(&mut rand::thread_rng()).unwrap())
-                .collect();
-            Series::new(col_name, data)
-        }
-        "KOM" => {
-            let data: Vec<i16> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| rand::thread_rng().gen_range(101..851))
-                .collect();
-            Series::new(col_name, data)
-        }
-        "STATSB" => {
-            let keys: Vec<i32> = STATSB.keys().cloned().collect();
-            let data: Vec<i32> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| *keys.choose(&mut rand::thread_rng()).unwrap())
-                .collect();
-            Series::new(col_name, data)
-        }
-        "AEGTE_ID" => {
-            let pnrs = create_bef_series("PNR", no_rows, year);
-            let ages = create_bef_series("ALDER", no_rows, year);
+impl Register for Bef {
+    fn name(&self) -> &'static str {
+        "bef"
+    }
 
-            let data: Vec<Option<String>> = pnrs
-                .str()
-                .unwrap()
-                .into_iter()
-                .zip(ages.i32().unwrap().into_iter())
-                .map(|(pnr, age)| {
-                    let _pnr = pnr.unwrap();
-                    let age = age.unwrap();
+    fn columns(&self) -> &'static [&'static str] {
+        &[
+            "PNR",
+            "FOED_DAG",
+            "ALDER",
+            "KOEN",
+            "CIVST",
+            "MOR_ID",
+            "FAR_ID",
+            "AEGTE_ID",
+            "E_FAELLE_ID",
+            "FAMILIE_ID",
+            "FAMILIE_TYPE",
+            "ANTBOERNF",
+            "ANTBOERNH",
+            "ANTPERSF",
+            "ANTPERSH",
+            "FM_MARK",
+            "HUSTYPE",
+            "PLADS",
+            "REG",
+            "KOM",
+            "STATSB",
+            "IE_TYPE",
+            "OPR_LAND",
+            "BOP_VFRA",
+            "CPRTJEK",
+            "VERSION",
+        ]
+    }
 
-                    let spouse_probability = match age {
-                        0..=17 => 0.0,  // No spouse for minors
-                        18..=25 => 0.1, // Low probability for young adults
-                        26..=35 => 0.5, // Higher probability for adults
-                        36..=60 => 0.7, // Highest probability for middle-aged
-                        _ => 0.6,       // Slightly lower for seniors
-                    };
+    /// One row per person living in Denmark at the end of the year.
+    fn pnrs(&self, population: &Population, _no_rows: usize, _seed: SeedStream) -> Vec<String> {
+        population
+            .persons()
+            .iter()
+            .map(|person| person.pnr.clone())
+            .collect()
+    }
 
-                    if rand::thread_rng().gen_bool(spouse_probability) {
-                        let spouse_birth_year = year - age - rand::thread_rng().gen_range(-5..=5);
-                        let spouse_birth_date = NaiveDate::from_ymd_opt(
-                            spouse_birth_year,
-                            rand::thread_rng().gen_range(1..=12),
-                            rand::thread_rng().gen_range(1..=28),
-                        )
-                        .unwrap();
-                        Some(get_pnr_for_birth_date(spouse_birth_date))
-                    } else {
-                        None
-                    }
-                })
-                .collect();
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        let records = create_bef_records(ctx);
+        build_frame(self.name(), schema, ctx, |col_name| match col_name {
+            // BEF of a year is delivered the year after
+            "VERSION" => Some(Series::new(
+                col_name.into(),
+                vec![(ctx.year + 1).to_string(); records.len()],
+            )),
+            _ => create_bef_series(col_name, &records),
+        })
+    }
 
-            Series::new(col_name, data)
-        }
-        _ => panic!("Unsupported column: {}", col_name),
+    /// BEF is the population at the end of the year, hence the December suffix.
+    fn file_stem(&self, year: i32) -> String {
+        format!("{}12", year)
     }
 }
+
+/// Builds one BEF record per person in the population.
+pub fn create_bef_records(ctx: &RegisterContext) -> Vec<BefRecord> {
+    let families = family_positions(ctx.population);
+    ctx.population
+        .persons()
+        .iter()
+        .zip(&families)
+        .map(|(person, family)| create_bef_record(person, family, ctx.year))
+        .collect()
+}
+
+pub fn create_bef_series(col_name: &str, records: &[BefRecord]) -> Option<Series> {
+    let col_name = PlSmallStr::from(col_name);
+
+    let series = match col_name.as_str() {
+        "PNR" => Series::new(
+            col_name,
+            records.iter().map(|r| r.pnr.clone()).collect::<Vec<_>>(),
+        ),
+        "FOED_DAG" => Series::new(
+            col_name,
+            records.iter().map(|r| r.foed_dag).collect::<Vec<_>>(),
+        ),
+        "ALDER" => Series::new(
+            col_name,
+            records.iter().map(|r| r.alder).collect::<Vec<_>>(),
+        ),
+        "KOEN" => Series::new(
+            col_name,
+            records
+                .iter()
+                .map(|r| r.koen.to_string())
+                .collect::<Vec<_>>(),
+        ),
+        "CIVST" => Series::new(
+            col_name,
+            records.iter().map(|r| r.civst).collect::<Vec<_>>(),
+        ),
+        "MOR_ID" => Series::new(
+            col_name,
+            records.iter().map(|r| r.mor_id.clone()).collect::<Vec<_>>(),
+        ),
+        "FAR_ID" => Series::new(
+            col_name,
+            records.iter().map(|r| r.far_id.clone()).collect::<Vec<_>>(),
+        ),
+        "AEGTE_ID" => Series::new(
+            col_name,
+            records
+                .iter()
+                .map(|r| r.aegte_id.clone())
+                .collect::<Vec<_>>(),
+        ),
+        "E_FAELLE_ID" => Series::new(
+            col_name,
+            records
+                .iter()
+                .map(|r| r.e_faelle_id.clone())
+                .collect::<Vec<_>>(),
+        ),
+        "FAMILIE_ID" => Series::new(
+            col_name,
+            records
+                .iter()
+                .map(|r| r.familie_id.clone())
+                .collect::<Vec<_>>(),
+        ),
+        "FAMILIE_TYPE" => Series::new(
+            col_name,
+            records.iter().map(|r| r.familie_type).collect::<Vec<_>>(),
+        ),
+        "ANTBOERNF" => Series::new(
+            col_name,
+            records.iter().map(|r| r.antboernf).collect::<Vec<_>>(),
+        ),
+        "ANTBOERNH" => Series::new(
+            col_name,
+            records.iter().map(|r| r.antboernh).collect::<Vec<_>>(),
+        ),
+        "ANTPERSF" => Series::new(
+            col_name,
+            records.iter().map(|r| r.antpersf).collect::<Vec<_>>(),
+        ),
+        "ANTPERSH" => Series::new(
+            col_name,
+            records.iter().map(|r| r.antpersh).collect::<Vec<_>>(),
+        ),
+        "FM_MARK" => Series::new(
+            col_name,
+            records.iter().map(|r| r.fm_mark).collect::<Vec<_>>(),
+        ),
+        "HUSTYPE" => Series::new(
+            col_name,
+            records.iter().map(|r| r.hustype).collect::<Vec<_>>(),
+        ),
+        "PLADS" => Series::new(
+            col_name,
+            records.iter().map(|r| r.plads).collect::<Vec<_>>(),
+        ),
+        "REG" => Series::new(col_name, records.iter().map(|r| r.reg).collect::<Vec<_>>()),
+        "KOM" => Series::new(col_name, records.iter().map(|r| r.kom).collect::<Vec<_>>()),
+        "STATSB" => Series::new(
+            col_name,
+            records.iter().map(|r| r.statsb).collect::<Vec<_>>(),
+        ),
+        "IE_TYPE" => Series::new(
+            col_name,
+            records.iter().map(|r| r.ie_type).collect::<Vec<_>>(),
+        ),
+        "OPR_LAND" => Series::new(
+            col_name,
+            records
+                .iter()
+                .map(|r| r.opr_land.clone())
+                .collect::<Vec<_>>(),
+        ),
+        "BOP_VFRA" => Series::new(
+            col_name,
+            records.iter().map(|r| r.bop_vfra).collect::<Vec<_>>(),
+        ),
+        "CPRTJEK" => Series::new(
+            col_name,
+            records.iter().map(|r| r.cprtjek).collect::<Vec<_>>(),
+        ),
+        _ => return None,
+    };
+    Some(series)
+}
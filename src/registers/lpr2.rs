@@ -1,241 +1,294 @@
-use crate::generate::pnr::get_pnr_for_birth_date;
-use crate::generate::recnum::generate_recnum;
-use crate::generate::recnum::get_recnum_for_pnr;
-use crate::generate::utils::generate_date_for_year;
+use crate::error::DataGeneratorError;
+use crate::generate::columns::build_frame;
+use crate::generate::context::RegisterContext;
+use crate::generate::pnr::cprtjek;
+use crate::generate::population::Population;
+use crate::generate::rng::SeedStream;
+use crate::generate::schema::Schema;
 use crate::generate::utils::get_random_diagnosis;
-use crate::registers::bef::create_bef_series;
+use crate::registers::Register;
 use chrono::NaiveDate;
 use polars::prelude::*;
 use rand::seq::SliceRandom;
 use rand::Rng;
-use rayon::prelude::*;
+use std::ops::RangeInclusive;
 
-pub fn create_lpr_diag_series(col_name: &str, no_rows: usize, year: i32) -> Series {
+pub struct LprDiag;
+
+impl Register for LprDiag {
+    fn name(&self) -> &'static str {
+        "lpr_diag"
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        &[
+            "C_DIAG",
+            "C_DIAGTYPE",
+            "C_TILDIAG",
+            "LEVERANCEDATO",
+            "RECNUM",
+            "VERSION",
+        ]
+    }
+
+    /// LPR3 replaced LPR2 in 2019.
+    fn years(&self) -> RangeInclusive<i32> {
+        i32::MIN..=2018
+    }
+
+    /// Every row belongs to an admission and reuses its RECNUM.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["lpr_adm"]
+    }
+
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        build_frame(self.name(), schema, ctx, |col_name| {
+            create_lpr_diag_series(col_name, ctx)
+        })
+    }
+}
+
+pub fn create_lpr_diag_series(col_name: &str, ctx: &RegisterContext) -> Option<Series> {
     let col_name = PlSmallStr::from(col_name);
+    let no_rows = ctx.no_rows();
+    let seed = ctx.seed.derive(col_name.as_str());
 
-    match col_name.as_str() {
+    let series = match col_name.as_str() {
         "C_DIAG" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| get_random_diagnosis())
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| get_random_diagnosis(rng));
             Series::new(col_name, data)
         }
         "C_DIAGTYPE" => {
             let types = ["A", "B", "H", "M", "G"];
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| types.choose(&mut rand::thread_rng()).unwrap().to_string())
-                .collect();
+            let data: Vec<String> =
+                seed.par_rows(no_rows, |rng, _| types.choose(rng).unwrap().to_string());
             Series::new(col_name, data)
         }
         "C_TILDIAG" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| get_random_diagnosis())
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| get_random_diagnosis(rng));
             Series::new(col_name, data)
         }
         "LEVERANCEDATO" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| generate_date_for_year(year))
-                .collect();
-            Series::new(col_name, data)
-        }
-        "RECNUM" => {
-            let pnrs = create_bef_series("PNR", no_rows, year);
-            let data: Vec<String> = pnrs
-                .str()
-                .unwrap()
-                .into_iter()
-                .map(|pnr| get_recnum_for_pnr(pnr.unwrap(), year))
-                .collect();
+            let data: Vec<NaiveDate> = seed.par_rows(no_rows, |rng, i| ctx.date_for_row(i, rng));
             Series::new(col_name, data)
         }
+        "RECNUM" => Series::new(col_name, ctx.contacts.clone()),
         "VERSION" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:04}", rand::thread_rng().gen_range(2000..2023)))
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                format!("{:04}", rng.gen_range(2000..2023))
+            });
             Series::new(col_name, data)
         }
-        _ => panic!("Unsupported LPR_DIAG column: {}", col_name),
+        _ => return None,
+    };
+    Some(series)
+}
+
+pub struct LprBes;
+
+impl Register for LprBes {
+    fn name(&self) -> &'static str {
+        "lpr_bes"
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        &["D_AMBDTO", "LEVERANCEDATO", "RECNUM", "VERSION"]
+    }
+
+    /// LPR3 replaced LPR2 in 2019.
+    fn years(&self) -> RangeInclusive<i32> {
+        i32::MIN..=2018
+    }
+
+    /// Every row belongs to an admission and reuses its RECNUM.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["lpr_adm"]
+    }
+
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        build_frame(self.name(), schema, ctx, |col_name| {
+            create_lpr_bes_series(col_name, ctx)
+        })
     }
 }
 
-pub fn create_lpr_bes_series(col_name: &str, no_rows: usize, year: i32) -> Series {
+pub fn create_lpr_bes_series(col_name: &str, ctx: &RegisterContext) -> Option<Series> {
     let col_name = PlSmallStr::from(col_name);
+    let no_rows = ctx.no_rows();
+    let seed = ctx.seed.derive(col_name.as_str());
 
-    match col_name.as_str() {
+    let series = match col_name.as_str() {
         "D_AMBDTO" | "LEVERANCEDATO" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| generate_date_for_year(year))
-                .collect();
-            Series::new(col_name, data)
-        }
-        "RECNUM" => {
-            let pnrs = create_bef_series("PNR", no_rows, year);
-            let data: Vec<String> = pnrs
-                .str()
-                .unwrap()
-                .into_iter()
-                .map(|pnr| get_recnum_for_pnr(pnr.unwrap(), year))
-                .collect();
+            let data: Vec<NaiveDate> = seed.par_rows(no_rows, |rng, i| ctx.date_for_row(i, rng));
             Series::new(col_name, data)
         }
+        "RECNUM" => Series::new(col_name, ctx.contacts.clone()),
         "VERSION" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:04}", rand::thread_rng().gen_range(2000..2023)))
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                format!("{:04}", rng.gen_range(2000..2023))
+            });
             Series::new(col_name, data)
         }
-        _ => panic!("Unsupported LPR_BES column: {}", col_name),
+        _ => return None,
+    };
+    Some(series)
+}
+
+pub struct LprAdm;
+
+impl Register for LprAdm {
+    fn name(&self) -> &'static str {
+        "lpr_adm"
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        &[
+            "PNR",
+            "C_ADIAG",
+            "C_AFD",
+            "C_HAFD",
+            "K_AFD",
+            "C_HENM",
+            "C_INDM",
+            "C_KONTAARS",
+            "C_UDM",
+            "C_HSGH",
+            "C_SGH",
+            "C_KOM",
+            "C_PATTYPE",
+            "C_SPEC",
+            "CPRTJEK",
+            "D_HENDTO",
+            "D_INDDTO",
+            "D_UDDTO",
+            "RECNUM",
+            "V_ALDDG",
+            "V_ALDER",
+            "V_INDMINUT",
+            "V_INDTIME",
+            "V_UDTIME",
+            "V_SENGDAGE",
+            "VERSION",
+        ]
+    }
+
+    /// LPR3 replaced LPR2 in 2019.
+    fn years(&self) -> RangeInclusive<i32> {
+        i32::MIN..=2018
+    }
+
+    /// Hospital contacts also belong to people who died during the year.
+    fn pnrs(&self, population: &Population, no_rows: usize, seed: SeedStream) -> Vec<String> {
+        population.sample_pnrs(no_rows, true, seed)
+    }
+
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        build_frame(self.name(), schema, ctx, |col_name| {
+            create_lpr_adm_series(col_name, ctx)
+        })
     }
 }
 
-pub fn create_lpr_adm_series(col_name: &str, no_rows: usize, year: i32) -> Series {
+pub fn create_lpr_adm_series(col_name: &str, ctx: &RegisterContext) -> Option<Series> {
     let col_name = PlSmallStr::from(col_name);
+    let pnrs = &ctx.pnrs;
+    let no_rows = ctx.no_rows();
+    let seed = ctx.seed.derive(col_name.as_str());
 
-    match col_name.as_str() {
-        "PNR" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    let birth_date = NaiveDate::from_ymd_opt(
-                        year - rand::thread_rng().gen_range(0..100),
-                        rand::thread_rng().gen_range(1..=12),
-                        rand::thread_rng().gen_range(1..=28),
-                    )
-                    .unwrap();
-                    get_pnr_for_birth_date(birth_date)
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
+    let series = match col_name.as_str() {
+        "PNR" => Series::new(col_name, pnrs.to_vec()),
         "C_ADIAG" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| get_random_diagnosis())
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| get_random_diagnosis(rng));
             Series::new(col_name, data)
         }
         "C_AFD" | "C_HAFD" | "K_AFD" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:04}", rand::thread_rng().gen_range(1000..9999)))
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                format!("{:04}", rng.gen_range(1000..9999))
+            });
             Series::new(col_name, data)
         }
         "C_HENM" | "C_INDM" | "C_KONTAARS" | "C_UDM" => {
             let codes = ["A", "B", "C", "D", "E"];
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| codes.choose(&mut rand::thread_rng()).unwrap().to_string())
-                .collect();
+            let data: Vec<String> =
+                seed.par_rows(no_rows, |rng, _| codes.choose(rng).unwrap().to_string());
             Series::new(col_name, data)
         }
         "C_HSGH" | "C_SGH" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:04}", rand::thread_rng().gen_range(1000..9999)))
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                format!("{:04}", rng.gen_range(1000..9999))
+            });
             Series::new(col_name, data)
         }
         "C_KOM" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:03}", rand::thread_rng().gen_range(100..999)))
-                .collect();
+            let data: Vec<i16> = seed.par_rows(no_rows, |rng, _| rng.gen_range(100..999));
             Series::new(col_name, data)
         }
         "C_PATTYPE" => {
             let types = ["0", "1", "2", "3"];
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| types.choose(&mut rand::thread_rng()).unwrap().to_string())
-                .collect();
+            let data: Vec<String> =
+                seed.par_rows(no_rows, |rng, _| types.choose(rng).unwrap().to_string());
             Series::new(col_name, data)
         }
         "C_SPEC" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:03}", rand::thread_rng().gen_range(1..100)))
-                .collect();
-            Series::new(col_name, data)
-        }
-        "CPRTJEK" | "CPRTYPE" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    if rand::thread_rng().gen_bool(0.5) {
-                        "V"
-                    } else {
-                        "U"
-                    }
-                    .to_string()
-                })
-                .collect();
+            let data: Vec<String> =
+                seed.par_rows(no_rows, |rng, _| format!("{:03}", rng.gen_range(1..100)));
             Series::new(col_name, data)
         }
+        "CPRTJEK" => Series::new(
+            col_name,
+            pnrs.iter().map(|pnr| cprtjek(pnr)).collect::<Vec<_>>(),
+        ),
         "D_HENDTO" | "D_INDDTO" | "D_UDDTO" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| generate_date_for_year(year))
-                .collect();
+            let data: Vec<NaiveDate> = seed.par_rows(no_rows, |rng, i| ctx.date_for_row(i, rng));
             Series::new(col_name, data)
         }
         "RECNUM" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| generate_recnum())
+            let mut recnums = ctx.recnums.lock().unwrap();
+            let data: Vec<String> = pnrs
+                .iter()
+                .map(|pnr| recnums.add_contact(LprAdm.name(), ctx.year, pnr))
                 .collect();
             Series::new(col_name, data)
         }
         "V_ALDDG" => {
-            let data: Vec<i32> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| rand::thread_rng().gen_range(0..36500))
-                .collect();
+            let data: Vec<i32> = seed.par_rows(no_rows, |rng, _| rng.gen_range(0..36500));
             Series::new(col_name, data)
         }
         "V_ALDER" => {
-            let data: Vec<i32> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| rand::thread_rng().gen_range(0..100))
-                .collect();
+            let data: Vec<i32> = seed.par_rows(no_rows, |rng, _| rng.gen_range(0..100));
             Series::new(col_name, data)
         }
         "V_INDMINUT" => {
-            let data: Vec<i32> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| rand::thread_rng().gen_range(0..60))
-                .collect();
+            let data: Vec<i32> = seed.par_rows(no_rows, |rng, _| rng.gen_range(0..60));
             Series::new(col_name, data)
         }
         "V_INDTIME" | "V_UDTIME" => {
-            let data: Vec<i32> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| rand::thread_rng().gen_range(0..24))
-                .collect();
+            let data: Vec<i32> = seed.par_rows(no_rows, |rng, _| rng.gen_range(0..24));
             Series::new(col_name, data)
         }
         "V_SENGDAGE" => {
-            let data: Vec<i32> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| rand::thread_rng().gen_range(0..100))
-                .collect();
+            let data: Vec<i32> = seed.par_rows(no_rows, |rng, _| rng.gen_range(0..100));
             Series::new(col_name, data)
         }
         "VERSION" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:04}", rand::thread_rng().gen_range(2000..2023)))
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                format!("{:04}", rng.gen_range(2000..2023))
+            });
             Series::new(col_name, data)
         }
-        _ => panic!("Unsupported LPR_ADM column: {}", col_name),
-    }
+        _ => return None,
+    };
+    Some(series)
 }
@@ -1,191 +1,225 @@
-use crate::generate::pnr::get_pnr_for_birth_date;
-use crate::generate::recnum::generate_recnum;
-use crate::generate::recnum::get_recnum_for_pnr;
-use crate::generate::utils::generate_date_for_year;
+use crate::error::DataGeneratorError;
+use crate::generate::columns::build_frame;
+use crate::generate::context::RegisterContext;
+use crate::generate::population::Population;
+use crate::generate::rng::SeedStream;
+use crate::generate::schema::Schema;
 use crate::generate::utils::get_random_diagnosis;
-use crate::registers::bef::create_bef_series;
+use crate::registers::Register;
 use chrono::{NaiveDate, NaiveTime};
 use polars::prelude::*;
 use rand::seq::SliceRandom;
 use rand::Rng;
-use rayon::prelude::*;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
-pub fn create_lpr3_kontakter_series(col_name: &str, no_rows: usize, year: i32) -> Series {
+pub struct Lpr3Kontakter;
+
+impl Register for Lpr3Kontakter {
+    fn name(&self) -> &'static str {
+        "lpr3_kontakter"
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        &[
+            "SORENHED_IND",
+            "SORENHED_HEN",
+            "SORENHED_ANS",
+            "DW_EK_KONTAKT",
+            "DW_EK_FORLOEB",
+            "CPR",
+            "dato_start",
+            "dato_slut",
+            "dato_behandling_start",
+            "dato_indberetning",
+            "tidspunkt_start",
+            "tidspunkt_slut",
+            "tidspunkt_behandling_start",
+            "aktionsdiagnose",
+            "kontaktaarsag",
+            "prioritet",
+            "kontakttype",
+            "henvisningsaarsag",
+            "henvisningsmaade",
+            "lprindberetningssytem",
+        ]
+    }
+
+    fn years(&self) -> RangeInclusive<i32> {
+        2019..=i32::MAX
+    }
+
+    /// Hospital contacts also belong to people who died during the year.
+    fn pnrs(&self, population: &Population, no_rows: usize, seed: SeedStream) -> Vec<String> {
+        population.sample_pnrs(no_rows, true, seed)
+    }
+
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        // Every row is a contact of its own, and a person's contacts during
+        // the year belong to the same course of care
+        let (kontakter, forloeb) = {
+            let mut recnums = ctx.recnums.lock().unwrap();
+            let mut courses: HashMap<&str, String> = HashMap::new();
+            ctx.pnrs
+                .iter()
+                .map(|pnr| {
+                    let kontakt = recnums.add_contact(self.name(), ctx.year, pnr);
+                    let forloeb = courses
+                        .entry(pnr.as_str())
+                        .or_insert_with(|| recnums.generate_recnum())
+                        .clone();
+                    (kontakt, forloeb)
+                })
+                .unzip::<_, _, Vec<_>, Vec<_>>()
+        };
+        build_frame(self.name(), schema, ctx, |col_name| match col_name {
+            "DW_EK_KONTAKT" => Some(Series::new(col_name.into(), kontakter.clone())),
+            "DW_EK_FORLOEB" => Some(Series::new(col_name.into(), forloeb.clone())),
+            _ => create_lpr3_kontakter_series(col_name, ctx),
+        })
+    }
+}
+
+pub fn create_lpr3_kontakter_series(col_name: &str, ctx: &RegisterContext) -> Option<Series> {
     let col_name = PlSmallStr::from(col_name);
+    let pnrs = &ctx.pnrs;
+    let no_rows = ctx.no_rows();
+    let seed = ctx.seed.derive(col_name.as_str());
 
-    match col_name.as_str() {
+    let series = match col_name.as_str() {
         "SORENHED_IND" | "SORENHED_HEN" | "SORENHED_ANS" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:06}", rand::thread_rng().gen_range(100000..999999)))
-                .collect();
-            Series::new(col_name, data)
-        }
-        "DW_EK_KONTAKT" | "DW_EK_FORLOEB" => {
-            let pnrs = create_bef_series("PNR", no_rows, year);
-            let data: Vec<String> = pnrs
-                .str()
-                .unwrap()
-                .into_iter()
-                .map(|pnr| get_recnum_for_pnr(pnr.unwrap(), year))
-                .collect();
-            Series::new(col_name, data)
-        }
-        "CPR" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    let birth_date = NaiveDate::from_ymd_opt(
-                        year - rand::thread_rng().gen_range(0..100),
-                        rand::thread_rng().gen_range(1..=12),
-                        rand::thread_rng().gen_range(1..=28),
-                    )
-                    .unwrap();
-                    get_pnr_for_birth_date(birth_date)
-                })
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                format!("{:06}", rng.gen_range(100000..999999))
+            });
             Series::new(col_name, data)
         }
+        "CPR" => Series::new(col_name, pnrs.to_vec()),
         "dato_start" | "dato_slut" | "dato_behandling_start" | "dato_indberetning" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    let date = generate_date_for_year(year);
-                    NaiveDate::parse_from_str(&date, "%Y-%m-%d")
-                        .unwrap()
-                        .format("%d%b%Y")
-                        .to_string()
-                })
-                .collect();
+            let data: Vec<NaiveDate> = seed.par_rows(no_rows, |rng, i| ctx.date_for_row(i, rng));
             Series::new(col_name, data)
         }
         "tidspunkt_start" | "tidspunkt_slut" | "tidspunkt_behandling_start" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    let hour = rand::thread_rng().gen_range(0..24);
-                    let minute = rand::thread_rng().gen_range(0..60);
-                    let second = rand::thread_rng().gen_range(0..60);
-                    let time = NaiveTime::from_hms_opt(hour, minute, second).unwrap();
-                    time.format("%H:%M:%S").to_string()
-                })
-                .collect();
+            let data: Vec<NaiveTime> = seed.par_rows(no_rows, |rng, _| {
+                let hour = rng.gen_range(0..24);
+                let minute = rng.gen_range(0..60);
+                let second = rng.gen_range(0..60);
+                NaiveTime::from_hms_opt(hour, minute, second).unwrap()
+            });
             Series::new(col_name, data)
         }
         "aktionsdiagnose" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| get_random_diagnosis())
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| get_random_diagnosis(rng));
             Series::new(col_name, data)
         }
         "kontaktaarsag" => {
             let aarsager = ["ALCA00", "ALCA10", "ALCA20", "ALCA30", "ALCA40"];
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    aarsager
-                        .choose(&mut rand::thread_rng())
-                        .unwrap()
-                        .to_string()
-                })
-                .collect();
+            let data: Vec<String> =
+                seed.par_rows(no_rows, |rng, _| aarsager.choose(rng).unwrap().to_string());
             Series::new(col_name, data)
         }
         "prioritet" => {
             let prioriteter = ["ATA1", "ATA2", "ATA3"];
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    prioriteter
-                        .choose(&mut rand::thread_rng())
-                        .unwrap()
-                        .to_string()
-                })
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                prioriteter.choose(rng).unwrap().to_string()
+            });
             Series::new(col_name, data)
         }
         "kontakttype" => {
             let typer = ["ALCA00", "ALCA10", "ALCA20", "ALCA30", "ALCA40"];
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| typer.choose(&mut rand::thread_rng()).unwrap().to_string())
-                .collect();
+            let data: Vec<String> =
+                seed.par_rows(no_rows, |rng, _| typer.choose(rng).unwrap().to_string());
             Series::new(col_name, data)
         }
         "henvisningsaarsag" | "henvisningsmaade" => {
             let aarsager = ["ALCA00", "ALCA10", "ALCA20", "ALCA30", "ALCA40"];
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    aarsager
-                        .choose(&mut rand::thread_rng())
-                        .unwrap()
-                        .to_string()
-                })
-                .collect();
+            let data: Vec<String> =
+                seed.par_rows(no_rows, |rng, _| aarsager.choose(rng).unwrap().to_string());
             Series::new(col_name, data)
         }
         "lprindberetningssytem" => {
             let systems = ["PAS", "OPUS", "COSMIC", "EPJ", "MidtEPJ"];
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| systems.choose(&mut rand::thread_rng()).unwrap().to_string())
-                .collect();
+            let data: Vec<String> =
+                seed.par_rows(no_rows, |rng, _| systems.choose(rng).unwrap().to_string());
             Series::new(col_name, data)
         }
-        _ => panic!("Unsupported LPR3_KONTAKTER column: {}", col_name),
+        _ => return None,
+    };
+    Some(series)
+}
+
+pub struct Lpr3Diagnoser;
+
+impl Register for Lpr3Diagnoser {
+    fn name(&self) -> &'static str {
+        "lpr3_diagnoser"
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        &[
+            "DW_EK_KONTAKT",
+            "diagnosekode",
+            "diagnosekode_parent",
+            "diagnosetype",
+            "diagnosetype_parent",
+            "senere_afkraeftet",
+            "lprindberetningssystem",
+        ]
+    }
+
+    fn years(&self) -> RangeInclusive<i32> {
+        2019..=i32::MAX
+    }
+
+    /// Every diagnosis belongs to a contact and reuses its DW_EK_KONTAKT.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["lpr3_kontakter"]
+    }
+
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        build_frame(self.name(), schema, ctx, |col_name| {
+            create_lpr3_diagnoser_series(col_name, ctx)
+        })
     }
 }
 
-pub fn create_lpr3_diagnoser_series(col_name: &str, no_rows: usize) -> Series {
+pub fn create_lpr3_diagnoser_series(col_name: &str, ctx: &RegisterContext) -> Option<Series> {
     let col_name = PlSmallStr::from(col_name);
+    let no_rows = ctx.no_rows();
+    let seed = ctx.seed.derive(col_name.as_str());
 
-    match col_name.as_str() {
-        "DW_EK_KONTAKT" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| generate_recnum())
-                .collect();
-            Series::new(col_name, data)
-        }
+    let series = match col_name.as_str() {
+        "DW_EK_KONTAKT" => Series::new(col_name, ctx.contacts.clone()),
         "diagnosekode" | "diagnosekode_parent" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| get_random_diagnosis())
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| get_random_diagnosis(rng));
             Series::new(col_name, data)
         }
         "diagnosetype" | "diagnosetype_parent" => {
             let types = ["A", "B", "H", "M", "G"];
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| types.choose(&mut rand::thread_rng()).unwrap().to_string())
-                .collect();
+            let data: Vec<String> =
+                seed.par_rows(no_rows, |rng, _| types.choose(rng).unwrap().to_string());
             Series::new(col_name, data)
         }
         "senere_afkraeftet" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    if rand::thread_rng().gen_bool(0.1) {
-                        "1"
-                    } else {
-                        "0"
-                    }
-                    .to_string()
-                })
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                if rng.gen_bool(0.1) { "1" } else { "0" }.to_string()
+            });
             Series::new(col_name, data)
         }
         "lprindberetningssystem" => {
             let systems = ["LPR3", "OPUS", "COSMIC", "EPJ", "MidtEPJ"];
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| systems.choose(&mut rand::thread_rng()).unwrap().to_string())
-                .collect();
+            let data: Vec<String> =
+                seed.par_rows(no_rows, |rng, _| systems.choose(rng).unwrap().to_string());
             Series::new(col_name, data)
         }
-        _ => panic!("Unsupported LPR3_DIAGNOSER column: {}", col_name),
-    }
+        _ => return None,
+    };
+    Some(series)
 }
@@ -5,5 +5,137 @@ mod other;
 
 pub use bef::*;
 pub use lpr2::*;
-pub use lpr3::{create_lpr3_diagnoser_series, create_lpr3_kontakter_series};
+pub use lpr3::{
+    create_lpr3_diagnoser_series, create_lpr3_kontakter_series, Lpr3Diagnoser, Lpr3Kontakter,
+};
 pub use other::*;
+
+use crate::error::DataGeneratorError;
+use crate::generate::context::RegisterContext;
+use crate::generate::population::Population;
+use crate::generate::rng::SeedStream;
+use crate::generate::schema::Schema;
+use polars::prelude::DataFrame;
+use std::ops::RangeInclusive;
+
+/// A register the generator can produce.
+///
+/// Adding a register means implementing this trait next to its series
+/// function, adding it to [`REGISTERS`] and writing its schema.
+pub trait Register: Sync {
+    /// The name used on the command line, for the schema and for the output directory.
+    fn name(&self) -> &'static str;
+
+    /// The columns the register's own code produces. Schema columns outside
+    /// this list need a generator in the schema.
+    fn columns(&self) -> &'static [&'static str];
+
+    /// The name of the schema file of the register.
+    fn schema_file(&self) -> String {
+        format!("{}.json", self.name())
+    }
+
+    /// The years the register exists for. Other years are skipped.
+    fn years(&self) -> RangeInclusive<i32> {
+        i32::MIN..=i32::MAX
+    }
+
+    /// Registers whose contacts the rows of this one belong to, reusing
+    /// their keys. They are generated first and must be requested too.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// The person behind each row of one year. Registers with dependencies
+    /// take theirs from the contacts they belong to instead.
+    fn pnrs(&self, population: &Population, no_rows: usize, seed: SeedStream) -> Vec<String> {
+        population.sample_pnrs(no_rows, false, seed)
+    }
+
+    /// Generates the rows of one year.
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError>;
+
+    /// The name of the file holding one year, without the extension of the
+    /// output format.
+    fn file_stem(&self, year: i32) -> String {
+        year.to_string()
+    }
+}
+
+/// Every register the generator knows about.
+pub static REGISTERS: &[&dyn Register] = &[
+    &Bef,
+    &Akm,
+    &Idan,
+    &Ind,
+    &Uddf,
+    &Dod,
+    &Vnds,
+    &LprAdm,
+    &LprBes,
+    &LprDiag,
+    &Lpr3Kontakter,
+    &Lpr3Diagnoser,
+];
+
+/// Looks up a register by name.
+pub fn find_register(name: &str) -> Result<&'static dyn Register, DataGeneratorError> {
+    REGISTERS
+        .iter()
+        .find(|register| register.name() == name)
+        .copied()
+        .ok_or_else(|| DataGeneratorError::UnknownRegister {
+            name: name.to_string(),
+            available: REGISTERS
+                .iter()
+                .map(|register| register.name())
+                .collect::<Vec<_>>()
+                .join(", "),
+        })
+}
+
+/// Looks up the requested registers and orders them so that every register
+/// comes after the registers it depends on, which must be requested too.
+pub fn resolve_registers(
+    names: &[String],
+) -> Result<Vec<&'static dyn Register>, DataGeneratorError> {
+    let requested = names
+        .iter()
+        .map(|name| find_register(name))
+        .collect::<Result<Vec<_>, _>>()?;
+    for register in &requested {
+        for dependency in register.dependencies() {
+            if !requested.iter().any(|r| r.name() == *dependency) {
+                return Err(DataGeneratorError::MissingDependency {
+                    name: register.name().to_string(),
+                    dependency: dependency.to_string(),
+                });
+            }
+        }
+    }
+
+    let mut ordered: Vec<&'static dyn Register> = Vec::with_capacity(requested.len());
+    fn visit(
+        register: &'static dyn Register,
+        requested: &[&'static dyn Register],
+        ordered: &mut Vec<&'static dyn Register>,
+    ) {
+        if ordered.iter().any(|r| r.name() == register.name()) {
+            return;
+        }
+        for dependency in register.dependencies() {
+            if let Some(dependency) = requested.iter().find(|r| r.name() == *dependency) {
+                visit(*dependency, requested, ordered);
+            }
+        }
+        ordered.push(register);
+    }
+    for register in &requested {
+        visit(*register, &requested, &mut ordered);
+    }
+    Ok(ordered)
+}
@@ -1,298 +1,387 @@
-use crate::generate::mappings::{BESKST13, JOBKAT, PRE_SOCIO, SOCIO13, STILL, TILKNYT};
-use chrono::NaiveDate;
+use crate::error::DataGeneratorError;
+use crate::generate::columns::build_frame;
+use crate::generate::context::RegisterContext;
+use crate::generate::mappings::STILL;
+use crate::generate::pnr::{cprtjek, decode_pnr};
+use crate::generate::population::Population;
+use crate::generate::rng::SeedStream;
+use crate::generate::schema::Schema;
+use crate::registers::Register;
+use chrono::{Duration, NaiveDate};
 use polars::prelude::*;
 use rand::seq::SliceRandom;
 use rand::Rng;
-use rayon::prelude::*;
+use std::collections::HashSet;
 
-pub fn create_akm_series(col_name: &str, no_rows: usize) -> Series {
+pub struct Akm;
+
+impl Register for Akm {
+    fn name(&self) -> &'static str {
+        "akm"
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        &["PNR", "CPRTJEK", "VERSION", "SENR"]
+    }
+
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        build_frame(self.name(), schema, ctx, |col_name| {
+            create_akm_series(col_name, ctx)
+        })
+    }
+}
+
+pub fn create_akm_series(col_name: &str, ctx: &RegisterContext) -> Option<Series> {
     let col_name = PlSmallStr::from(col_name);
+    let pnrs = &ctx.pnrs;
+    let no_rows = ctx.no_rows();
+    let seed = ctx.seed.derive(col_name.as_str());
 
-    match col_name.as_str() {
-        "PNR" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    format!(
-                        "{:010}",
-                        rand::thread_rng().gen_range(100000000_u32..999999999_u32)
-                    )
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
-        "SOCIO" | "SOCIO02" | "SOCIO13" => {
-            let socio_keys: Vec<i32> = SOCIO13.keys().cloned().collect();
-            let data: Vec<i32> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| *socio_keys.choose(&mut rand::thread_rng()).unwrap())
-                .collect();
-            Series::new(col_name, data)
-        }
-        "CPRTJEK" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    ["V", "U"]
-                        .choose(&mut rand::thread_rng())
-                        .unwrap()
-                        .to_string()
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
-        "CPRTYPE" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    ["A", "B", "C", "D", "E", "F"]
-                        .choose(&mut rand::thread_rng())
-                        .unwrap()
-                        .to_string()
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
+    let series = match col_name.as_str() {
+        "PNR" => Series::new(col_name, pnrs.to_vec()),
+        "CPRTJEK" => Series::new(
+            col_name,
+            pnrs.iter().map(|pnr| cprtjek(pnr)).collect::<Vec<_>>(),
+        ),
         "VERSION" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:04}", rand::thread_rng().gen_range(2000..2023)))
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                format!("{:04}", rng.gen_range(2000..2023))
+            });
             Series::new(col_name, data)
         }
         "SENR" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:06}", rand::thread_rng().gen_range(100000..999999)))
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                format!("{:06}", rng.gen_range(100000..999999))
+            });
             Series::new(col_name, data)
         }
-        _ => panic!("Unsupported AKM column: {}", col_name),
+        _ => return None,
+    };
+    Some(series)
+}
+
+pub struct Dod;
+
+impl Register for Dod {
+    fn name(&self) -> &'static str {
+        "dod"
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        &["PNR", "DODDATO"]
+    }
+
+    /// One row per death during the year.
+    fn pnrs(&self, population: &Population, _no_rows: usize, _seed: SeedStream) -> Vec<String> {
+        population.deaths().keys().cloned().collect()
+    }
+
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        build_frame(self.name(), schema, ctx, |col_name| {
+            create_dod_series(col_name, ctx)
+        })
     }
 }
 
-pub fn create_uddf_series(col_name: &str, no_rows: usize) -> Series {
+pub fn create_dod_series(col_name: &str, ctx: &RegisterContext) -> Option<Series> {
     let col_name = PlSmallStr::from(col_name);
+    let pnrs = &ctx.pnrs;
 
-    match col_name.as_str() {
-        "PNR" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    format!(
-                        "{:010}",
-                        rand::thread_rng().gen_range(100000000_u32..999999999_u32)
-                    )
-                })
+    let series = match col_name.as_str() {
+        "PNR" => Series::new(col_name, pnrs.to_vec()),
+        "DODDATO" => {
+            let data: Vec<NaiveDate> = pnrs
+                .iter()
+                .map(|pnr| ctx.population.death_date(pnr).unwrap())
                 .collect();
             Series::new(col_name, data)
         }
-        "CPRTJEK" | "CPRTYPE" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    ["V", "U"]
-                        .choose(&mut rand::thread_rng())
-                        .unwrap()
-                        .to_string()
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
-        "HFAUDD" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    let isced_level = rand::thread_rng().gen_range(1..=9);
-                    format!("{}", isced_level)
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
-        "HF_KILDE" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    ["A", "B", "C", "D", "E"]
-                        .choose(&mut rand::thread_rng())
-                        .unwrap()
-                        .to_string()
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
-        "HF_VFRA" | "HF_VTIL" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    let year = rand::thread_rng().gen_range(1900..2023);
-                    let month = rand::thread_rng().gen_range(1..13);
-                    let day = rand::thread_rng().gen_range(1..29);
-                    NaiveDate::from_ymd_opt(year, month, day)
-                        .unwrap()
-                        .format("%Y%m%d")
-                        .to_string()
-                })
-                .collect();
+        _ => return None,
+    };
+    Some(series)
+}
+
+pub struct Vnds;
+
+impl Register for Vnds {
+    fn name(&self) -> &'static str {
+        "vnds"
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        &["PNR", "INDUD_KODE", "HAEND_DATO"]
+    }
+
+    /// One row per move into or out of Denmark during the year.
+    fn pnrs(&self, population: &Population, _no_rows: usize, _seed: SeedStream) -> Vec<String> {
+        population
+            .migrations()
+            .iter()
+            .map(|migration| migration.pnr.clone())
+            .collect()
+    }
+
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        build_frame(self.name(), schema, ctx, |col_name| {
+            create_vnds_series(col_name, ctx)
+        })
+    }
+}
+
+pub fn create_vnds_series(col_name: &str, ctx: &RegisterContext) -> Option<Series> {
+    let col_name = PlSmallStr::from(col_name);
+    let migrations = ctx.population.migrations();
+
+    let series = match col_name.as_str() {
+        "PNR" => Series::new(col_name, ctx.pnrs.to_vec()),
+        "INDUD_KODE" => {
+            let data: Vec<&str> = migrations.iter().map(|m| m.direction.code()).collect();
             Series::new(col_name, data)
         }
-        "INSTNR" => {
-            let data: Vec<i8> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| rand::thread_rng().gen_range(1..100))
-                .collect();
+        "HAEND_DATO" => {
+            let data: Vec<NaiveDate> = migrations.iter().map(|m| m.date).collect();
             Series::new(col_name, data)
         }
-        "VERSION" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:04}", rand::thread_rng().gen_range(2000..2023)))
-                .collect();
-            Series::new(col_name, data)
-        }
-        _ => panic!("Unsupported UDDF column: {}", col_name),
+        _ => return None,
+    };
+    Some(series)
+}
+
+pub struct Uddf;
+
+impl Register for Uddf {
+    fn name(&self) -> &'static str {
+        "uddf"
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        &[
+            "PNR", "CPRTJEK", "HFAUDD", "HF_KILDE", "HF_VFRA", "HF_VTIL", "INSTNR", "VERSION",
+        ]
+    }
+
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        // The education is completed after the person's birth and no later
+        // than the register year, and stays valid from then on
+        let year_end = NaiveDate::from_ymd_opt(ctx.year, 12, 31).unwrap();
+        let periods = ctx
+            .seed
+            .derive("HF_VFRA")
+            .par_rows(ctx.no_rows(), |rng, i| {
+                let birth_date =
+                    decode_pnr(&ctx.pnrs[i]).map_or(year_end, |pnr| pnr.birth_date.min(year_end));
+                let valid_from = date_between(birth_date, year_end, rng);
+                (valid_from, date_between(valid_from, year_end, rng))
+            });
+        build_frame(self.name(), schema, ctx, |col_name| {
+            create_uddf_series(col_name, ctx, &periods)
+        })
     }
 }
 
-pub fn create_ind_series(col_name: &str, no_rows: usize) -> Series {
+/// A random date from `first` to `last`, both included.
+fn date_between(first: NaiveDate, last: NaiveDate, rng: &mut impl Rng) -> NaiveDate {
+    first + Duration::days(rng.gen_range(0..=(last - first).num_days()))
+}
+
+pub fn create_uddf_series(
+    col_name: &str,
+    ctx: &RegisterContext,
+    periods: &[(NaiveDate, NaiveDate)],
+) -> Option<Series> {
     let col_name = PlSmallStr::from(col_name);
+    let pnrs = &ctx.pnrs;
+    let no_rows = ctx.no_rows();
+    let seed = ctx.seed.derive(col_name.as_str());
 
-    match col_name.as_str() {
-        "BESKST13" => {
-            let keys: Vec<i32> = BESKST13.keys().cloned().collect();
-            let data: Vec<i32> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| *keys.choose(&mut rand::thread_rng()).unwrap())
-                .collect();
-            Series::new(col_name, data)
-        }
-        "CPRTJEK" | "CPRTYPE" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    ["V", "U"]
-                        .choose(&mut rand::thread_rng())
-                        .unwrap()
-                        .to_string()
-                })
-                .collect();
+    let series = match col_name.as_str() {
+        "PNR" => Series::new(col_name, pnrs.to_vec()),
+        "CPRTJEK" => Series::new(
+            col_name,
+            pnrs.iter().map(|pnr| cprtjek(pnr)).collect::<Vec<_>>(),
+        ),
+        "HFAUDD" => {
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                let isced_level = rng.gen_range(1..=9);
+                format!("{}", isced_level)
+            });
             Series::new(col_name, data)
         }
-        "LOENMV_13" => {
-            let data: Vec<f64> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| rand::thread_rng().gen_range(0.0..1_000_000.0))
-                .collect();
+        "HF_KILDE" => {
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                ["A", "B", "C", "D", "E"].choose(rng).unwrap().to_string()
+            });
             Series::new(col_name, data)
         }
-        "PERINDKIALT_13" => {
-            let data: Vec<f64> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| rand::thread_rng().gen_range(0.0..2_000_000.0))
-                .collect();
+        "HF_VFRA" => Series::new(
+            col_name,
+            periods.iter().map(|(from, _)| *from).collect::<Vec<_>>(),
+        ),
+        "HF_VTIL" => Series::new(
+            col_name,
+            periods.iter().map(|(_, to)| *to).collect::<Vec<_>>(),
+        ),
+        "INSTNR" => {
+            let data: Vec<i8> = seed.par_rows(no_rows, |rng, _| rng.gen_range(1..100));
             Series::new(col_name, data)
         }
-        "PNR" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    format!(
-                        "{:010}",
-                        rand::thread_rng().gen_range(100000000_u32..999999999_u32)
-                    )
-                })
-                .collect();
+        "VERSION" => {
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                format!("{:04}", rng.gen_range(2000..2023))
+            });
             Series::new(col_name, data)
         }
-        "PRE_SOCIO" => {
-            let keys: Vec<i32> = PRE_SOCIO.keys().cloned().collect();
-            let data: Vec<i32> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| *keys.choose(&mut rand::thread_rng()).unwrap())
+        _ => return None,
+    };
+    Some(series)
+}
+
+pub struct Ind;
+
+impl Register for Ind {
+    fn name(&self) -> &'static str {
+        "ind"
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        &["CPRTJEK", "LOENMV_13", "PERINDKIALT_13", "PNR", "VERSION"]
+    }
+
+    /// A sample of the residents at the end of the year, together with
+    /// everyone who emigrated during it and so was taxed for part of it. If
+    /// more people emigrated than there are rows, the rows are a sample of
+    /// the emigrants.
+    fn pnrs(&self, population: &Population, no_rows: usize, seed: SeedStream) -> Vec<String> {
+        let emigrants: Vec<&str> = population.emigrants().collect();
+        if emigrants.len() >= no_rows {
+            let mut rng = seed.derive("emigrants").rng(0);
+            return emigrants
+                .choose_multiple(&mut rng, no_rows)
+                .map(|pnr| pnr.to_string())
                 .collect();
-            Series::new(col_name, data)
         }
+        let mut pnrs = population.sample_pnrs(no_rows - emigrants.len(), false, seed);
+        pnrs.extend(emigrants.into_iter().map(str::to_string));
+        pnrs
+    }
+
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        // People abroad at the end of the year have no income in the register
+        let emigrants: HashSet<&str> = ctx.population.emigrants().collect();
+        build_frame(self.name(), schema, ctx, |col_name| {
+            create_ind_series(col_name, ctx, &emigrants)
+        })
+    }
+}
+
+pub fn create_ind_series(
+    col_name: &str,
+    ctx: &RegisterContext,
+    emigrants: &HashSet<&str>,
+) -> Option<Series> {
+    let col_name = PlSmallStr::from(col_name);
+    let pnrs = &ctx.pnrs;
+    let no_rows = ctx.no_rows();
+    let seed = ctx.seed.derive(col_name.as_str());
+
+    let income = |max: f64| -> Vec<Option<f64>> {
+        seed.par_rows(no_rows, |rng, i| {
+            let income = rng.gen_range(0.0..max);
+            (!emigrants.contains(pnrs[i].as_str())).then_some(income)
+        })
+    };
+
+    let series = match col_name.as_str() {
+        "CPRTJEK" => Series::new(
+            col_name,
+            pnrs.iter().map(|pnr| cprtjek(pnr)).collect::<Vec<_>>(),
+        ),
+        "LOENMV_13" => Series::new(col_name, income(1_000_000.0)),
+        "PERINDKIALT_13" => Series::new(col_name, income(2_000_000.0)),
+        "PNR" => Series::new(col_name, pnrs.to_vec()),
         "VERSION" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| format!("{:04}", rand::thread_rng().gen_range(2000..2023)))
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                format!("{:04}", rng.gen_range(2000..2023))
+            });
             Series::new(col_name, data)
         }
-        _ => panic!("Unsupported IND column: {}", col_name),
+        _ => return None,
+    };
+    Some(series)
+}
+
+pub struct Idan;
+
+impl Register for Idan {
+    fn name(&self) -> &'static str {
+        "idan"
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        &[
+            "ARBGNR", "ARBNR", "CPRTJEK", "CVRNR", "LBNR", "PNR", "JOBLON", "STILL",
+        ]
+    }
+
+    fn generate_frame(
+        &self,
+        schema: &Schema,
+        ctx: &RegisterContext,
+    ) -> Result<DataFrame, DataGeneratorError> {
+        build_frame(self.name(), schema, ctx, |col_name| {
+            create_idan_series(col_name, ctx)
+        })
     }
 }
 
-pub fn create_idan_series(col_name: &str, no_rows: usize) -> Series {
+pub fn create_idan_series(col_name: &str, ctx: &RegisterContext) -> Option<Series> {
     let col_name = PlSmallStr::from(col_name);
+    let pnrs = &ctx.pnrs;
+    let no_rows = ctx.no_rows();
+    let seed = ctx.seed.derive(col_name.as_str());
 
-    match col_name.as_str() {
+    let series = match col_name.as_str() {
         "ARBGNR" | "ARBNR" | "CVRNR" | "LBNR" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    format!(
-                        "{:08}",
-                        rand::thread_rng().gen_range(10000000_u32..99999999_u32)
-                    )
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
-        "CPRTJEK" | "CPRTYPE" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    ["V", "U"]
-                        .choose(&mut rand::thread_rng())
-                        .unwrap()
-                        .to_string()
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
-        "PNR" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| {
-                    format!(
-                        "{:010}",
-                        rand::thread_rng().gen_range(100000000_u32..999999999_u32)
-                    )
-                })
-                .collect();
-            Series::new(col_name, data)
-        }
-        "JOBKAT" => {
-            let keys: Vec<i8> = JOBKAT.keys().cloned().collect();
-            let data: Vec<i8> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| *keys.choose(&mut rand::thread_rng()).unwrap())
-                .collect();
+            let data: Vec<String> = seed.par_rows(no_rows, |rng, _| {
+                format!("{:08}", rng.gen_range(10000000_u32..99999999_u32))
+            });
             Series::new(col_name, data)
         }
+        "CPRTJEK" => Series::new(
+            col_name,
+            pnrs.iter().map(|pnr| cprtjek(pnr)).collect::<Vec<_>>(),
+        ),
+        "PNR" => Series::new(col_name, pnrs.to_vec()),
         "JOBLON" => {
-            let data: Vec<f64> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| rand::thread_rng().gen_range(15000.0..100000.0))
-                .collect();
+            let data: Vec<f64> = seed.par_rows(no_rows, |rng, _| rng.gen_range(15000.0..100000.0));
             Series::new(col_name, data)
         }
         "STILL" => {
-            let data: Vec<String> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| STILL.choose(&mut rand::thread_rng()).unwrap().clone())
-                .collect();
+            let data: Vec<String> =
+                seed.par_rows(no_rows, |rng, _| STILL.choose(rng).unwrap().clone());
             Series::new(col_name, data)
         }
-        "TILKNYT" => {
-            let keys: Vec<i8> = TILKNYT.keys().cloned().collect();
-            let data: Vec<i8> = (0..no_rows)
-                .into_par_iter()
-                .map(|_| *keys.choose(&mut rand::thread_rng()).unwrap())
-                .collect();
-            Series::new(col_name, data)
-        }
-        _ => panic!("Unsupported IDAN column: {}", col_name),
-    }
+        _ => return None,
+    };
+    Some(series)
 }
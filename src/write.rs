@@ -1,10 +1,114 @@
 use crate::error::DataGeneratorError;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs::{self, File};
 use std::path::Path;
 
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
 use polars::prelude::*;
-use std::io::BufWriter;
+use polars_parquet::parquet::metadata::KeyValue;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+/// The file formats output can be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Parquet,
+    /// Delimited text, see the `--csv-*` options
+    Csv,
+    /// SAS transport file, see `--xport-version`
+    #[value(name = "xpt", alias = "xport")]
+    Xport,
+    /// Stata 14+ dataset, with value labels from the mappings
+    #[value(name = "dta", alias = "stata")]
+    Stata,
+    /// Arrow IPC file, also known as Feather v2, see `--ipc-compression`
+    #[value(alias = "arrow", alias = "feather")]
+    Ipc,
+    /// SQLite database with all registers, see `--sqlite-tables`. An existing
+    /// database is only replaced with `--overwrite`
+    #[value(alias = "sqlite3")]
+    Sqlite,
+}
+
+impl OutputFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Parquet => "Parquet",
+            OutputFormat::Csv => "CSV",
+            OutputFormat::Xport => "SAS transport",
+            OutputFormat::Stata => "Stata",
+            OutputFormat::Ipc => "Arrow IPC",
+            OutputFormat::Sqlite => "SQLite",
+        }
+    }
+}
+
+/// The character encoding of text in CSV and SAS transport output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TextEncoding {
+    #[value(alias = "utf-8")]
+    Utf8,
+    /// ISO-8859-1, which is what older SAS installations expect for æ, ø and å
+    #[value(alias = "iso-8859-1")]
+    Latin1,
+}
+
+/// How delimited text output is compressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CsvCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// How Arrow IPC output is compressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum IpcOutputCompression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl IpcOutputCompression {
+    fn to_polars(self) -> Option<IpcCompression> {
+        match self {
+            IpcOutputCompression::None => None,
+            IpcOutputCompression::Lz4 => Some(IpcCompression::LZ4),
+            IpcOutputCompression::Zstd => Some(IpcCompression::ZSTD),
+        }
+    }
+}
+
+/// How delimited text output is written.
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    /// Write decimals as `1,5` rather than `1.5`.
+    pub decimal_comma: bool,
+    /// A chrono format string for date columns.
+    pub date_format: String,
+    pub encoding: TextEncoding,
+    pub compression: CsvCompression,
+}
+
+impl CsvOptions {
+    /// The file extension, e.g. `csv`, or `tsv.gz` for gzipped tab-separated files.
+    pub fn extension(&self) -> String {
+        let extension = if self.delimiter == b'\t' {
+            "tsv"
+        } else {
+            "csv"
+        };
+        match self.compression {
+            CsvCompression::None => extension.to_string(),
+            CsvCompression::Gzip => format!("{}.gz", extension),
+            CsvCompression::Zstd => format!("{}.zst", extension),
+        }
+    }
+}
 
 pub fn write_dataframe_to_single_parquet(
     df: &mut DataFrame,
@@ -16,11 +120,151 @@ pub fn write_dataframe_to_single_parquet(
     Ok(())
 }
 
+/// Writes an Arrow IPC file, which tools can memory-map.
+pub fn write_dataframe_to_single_ipc(
+    df: &mut DataFrame,
+    file_path: &Path,
+    compression: IpcOutputCompression,
+) -> Result<(), DataGeneratorError> {
+    let file = File::create(file_path)?;
+    let writer = BufWriter::new(file);
+    IpcWriter::new(writer)
+        .with_compression(compression.to_polars())
+        .finish(df)?;
+    Ok(())
+}
+
+/// Writes a Parquet file with extra key-value pairs in its file metadata.
+pub fn write_dataframe_to_single_parquet_with_metadata(
+    df: &DataFrame,
+    file_path: &Path,
+    metadata: &[(String, String)],
+) -> Result<(), DataGeneratorError> {
+    let file = File::create(file_path)?;
+    let writer = BufWriter::new(file);
+    let mut batched = ParquetWriter::new(writer).batched(&df.schema())?;
+    batched.write_batch(df)?;
+
+    let key_value_metadata = metadata
+        .iter()
+        .map(|(key, value)| KeyValue {
+            key: key.clone(),
+            value: Some(value.clone()),
+        })
+        .collect();
+    batched
+        .get_writer()
+        .lock()
+        .unwrap()
+        .end(Some(key_value_metadata))?;
+    Ok(())
+}
+
+/// Writes a DataFrame as delimited text with a header row.
+pub fn write_dataframe_to_single_csv(
+    df: &DataFrame,
+    file_path: &Path,
+    options: &CsvOptions,
+) -> Result<(), DataGeneratorError> {
+    let mut df = if options.decimal_comma {
+        with_decimal_comma(df)?
+    } else {
+        df.clone()
+    };
+    let mut csv = Vec::new();
+    CsvWriter::new(&mut csv)
+        .include_header(true)
+        .with_separator(options.delimiter)
+        .with_date_format(Some(options.date_format.clone()))
+        .finish(&mut df)?;
+    let csv = match options.encoding {
+        TextEncoding::Utf8 => csv,
+        TextEncoding::Latin1 => {
+            let text = std::str::from_utf8(&csv)
+                .map_err(|e| DataGeneratorError::Other(format!("CSV output isn't UTF-8: {}", e)))?;
+            encode_text(text, TextEncoding::Latin1)?
+        }
+    };
+
+    let writer = BufWriter::new(File::create(file_path)?);
+    match options.compression {
+        CsvCompression::None => finish_text(writer, &csv)?,
+        CsvCompression::Gzip => {
+            let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+            encoder.write_all(&csv)?;
+            encoder.finish()?.flush()?;
+        }
+        CsvCompression::Zstd => {
+            let mut encoder = zstd::Encoder::new(writer, 0)?;
+            encoder.write_all(&csv)?;
+            encoder.finish()?.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn finish_text(mut writer: impl Write, text: &[u8]) -> std::io::Result<()> {
+    writer.write_all(text)?;
+    writer.flush()
+}
+
+/// Formats the float columns as text with a decimal comma, which the Polars
+/// CSV writer can't do itself.
+fn with_decimal_comma(df: &DataFrame) -> PolarsResult<DataFrame> {
+    let columns = df
+        .get_columns()
+        .iter()
+        .map(|column| match column.dtype() {
+            DataType::Float32 | DataType::Float64 => {
+                let values = column.cast(&DataType::Float64)?;
+                let text: StringChunked = values
+                    .f64()?
+                    .into_iter()
+                    .map(|value| value.map(|value| value.to_string().replace('.', ",")))
+                    .collect();
+                Ok(text.with_name(column.name().clone()).into_series())
+            }
+            _ => Ok(column.clone()),
+        })
+        .collect::<PolarsResult<Vec<_>>>()?;
+    DataFrame::new(columns)
+}
+
+/// Encodes text, failing on characters Latin-1 doesn't have when encoding
+/// as Latin-1.
+fn encode_text(text: &str, encoding: TextEncoding) -> Result<Vec<u8>, DataGeneratorError> {
+    match encoding {
+        TextEncoding::Utf8 => Ok(text.as_bytes().to_vec()),
+        TextEncoding::Latin1 => text
+            .chars()
+            .map(|c| {
+                u8::try_from(u32::from(c)).map_err(|_| {
+                    DataGeneratorError::Other(format!(
+                        "The character '{}' can't be written in Latin-1",
+                        c
+                    ))
+                })
+            })
+            .collect(),
+    }
+}
+
 pub fn cleanup_dataset_parquet_files(dataset_dir: &Path) -> Result<(), Box<dyn Error>> {
+    cleanup_dataset_files(dataset_dir, "parquet")
+}
+
+/// Removes the files of a dataset directory whose names end in `.{extension}`.
+pub fn cleanup_dataset_files(dataset_dir: &Path, extension: &str) -> Result<(), Box<dyn Error>> {
+    let suffix = format!(".{}", extension);
     if dataset_dir.exists() {
         for entry in fs::read_dir(dataset_dir)? {
             let path = entry?.path();
-            if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("parquet") {
+            if path.is_file()
+                && path
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|name| name.ends_with(&suffix))
+            {
                 fs::remove_file(path)?;
             }
         }
@@ -65,9 +309,8 @@ pub fn write_dataframe_to_multi_parquet(
     }
 
     let n_rows = df.height();
-    let mut part_number = 0;
 
-    for start in (0..n_rows).step_by(chunk_size) {
+    for (part_number, start) in (0..n_rows).step_by(chunk_size).enumerate() {
         let end = std::cmp::min(start + chunk_size, n_rows);
         let chunk = df.slice(start as i64, end - start);
 
@@ -76,7 +319,1618 @@ pub fn write_dataframe_to_multi_parquet(
 
         // write the chunk
         write_dataframe_chunk_to_parquet(&mut chunk_mut, dataset_id, base_dir, part_number)?;
-        part_number += 1;
     }
     Ok(df.clone())
 }
+
+/// Splits a DataFrame into files of `chunk_size` rows, named
+/// `part-00000.{extension}` and so on, which `write_file` writes.
+pub fn write_dataframe_to_multi_files(
+    df: &DataFrame,
+    dataset_id: &str,
+    base_dir: &Path,
+    chunk_size: usize,
+    extension: &str,
+    write_file: impl Fn(&DataFrame, &Path) -> Result<(), DataGeneratorError>,
+) -> Result<(), DataGeneratorError> {
+    let dataset_dir = base_dir.join(format!("dataset={}", dataset_id));
+
+    // create dataset directory if not exist, else clean up
+    if !dataset_dir.exists() {
+        fs::create_dir_all(&dataset_dir)?;
+    } else {
+        cleanup_dataset_files(&dataset_dir, extension)?;
+    }
+
+    for (part_number, start) in (0..df.height()).step_by(chunk_size).enumerate() {
+        let chunk = df.slice(start as i64, chunk_size);
+        let file_path = dataset_dir.join(format!("part-{:05}.{}", part_number, extension));
+        write_file(&chunk, &file_path)?;
+    }
+    Ok(())
+}
+
+/// The version of the SAS transport format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum XportVersion {
+    /// Readable with `LIBNAME XPORT`, with names of at most 8 characters
+    V5,
+    /// Readable with `%XPT2LOC`, with names of up to 32 characters
+    V8,
+}
+
+impl XportVersion {
+    fn max_name_length(&self) -> usize {
+        match self {
+            XportVersion::V5 => 8,
+            XportVersion::V8 => 32,
+        }
+    }
+
+    fn max_text_length(&self) -> usize {
+        match self {
+            XportVersion::V5 => 200,
+            XportVersion::V8 => 32767,
+        }
+    }
+}
+
+/// How SAS transport output is written.
+#[derive(Clone, Debug)]
+pub struct XportOptions {
+    pub version: XportVersion,
+    pub encoding: TextEncoding,
+}
+
+/// The SAS dataset held by a transport file.
+pub struct XportDataset {
+    /// The member name, which is shortened to fit the version.
+    pub name: String,
+    pub label: String,
+    /// Variable labels by column name. Columns without one whose name had
+    /// to be shortened are labelled with their full name.
+    pub column_labels: HashMap<String, String>,
+}
+
+/// Days from the SAS epoch, 1 January 1960, to the Unix epoch.
+const SAS_EPOCH_OFFSET_DAYS: i64 = 3653;
+const SECONDS_PER_DAY: i64 = 86_400;
+const SAS_VERSION: &str = "9.4";
+
+/// Writes a DataFrame as a SAS transport file with one dataset, following
+/// the record layout of SAS technical support document TS-140.
+///
+/// Numbers are written as 8-byte IBM floating point, dates as days since
+/// 1960 with the DATE9. format, times as seconds since midnight with TIME8.
+/// and datetimes as seconds since 1960 with DATETIME20., and nulls as the
+/// SAS missing value.
+pub fn write_dataframe_to_xport(
+    df: &DataFrame,
+    file_path: &Path,
+    dataset: &XportDataset,
+    options: &XportOptions,
+) -> Result<(), DataGeneratorError> {
+    let v8 = options.version == XportVersion::V8;
+    let column_names: Vec<&str> = df.get_columns().iter().map(|c| c.name().as_str()).collect();
+    let names = sas_names(&column_names, options.version);
+    let variables = df
+        .get_columns()
+        .iter()
+        .zip(names)
+        .map(|(column, name)| {
+            let label = match dataset.column_labels.get(column.name().as_str()) {
+                Some(label) => label.as_str(),
+                None if name != column.name().as_str() => column.name().as_str(),
+                None => "",
+            };
+            XportVariable::new(column, name, label, options)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let member = sas_names(&[&dataset.name], options.version)
+        .remove(0)
+        .to_ascii_uppercase();
+    let created = chrono::Local::now()
+        .format("%d%b%y:%H:%M:%S")
+        .to_string()
+        .to_uppercase();
+    let os = std::env::consts::OS.to_uppercase();
+    let os = &os[..os.len().min(8)];
+
+    let mut out = XportRecords::new(BufWriter::new(File::create(file_path)?));
+    out.header(if v8 { "LIBV8" } else { "LIBRARY" }, [0; 6])?;
+    out.write(
+        format!(
+            "{:<8}{:<8}{:<8}{:<8}{:<8}{:24}{}",
+            "SAS", "SAS", "SASLIB", SAS_VERSION, os, "", created
+        )
+        .as_bytes(),
+    )?;
+    out.write(format!("{}{:64}", created, "").as_bytes())?;
+
+    out.header(if v8 { "MEMBV8" } else { "MEMBER" }, [0, 0, 0, 160, 0, 140])?;
+    out.header(if v8 { "DSCPTV8" } else { "DSCRPTR" }, [0; 6])?;
+    let member_record = if v8 {
+        format!(
+            "{:<8}{:<32}{:<8}{:<8}{:<8}{}",
+            "SAS", member, "SASDATA", SAS_VERSION, os, created
+        )
+    } else {
+        format!(
+            "{:<8}{:<8}{:<8}{:<8}{:<8}{:24}{}",
+            "SAS", member, "SASDATA", SAS_VERSION, os, "", created
+        )
+    };
+    out.write(member_record.as_bytes())?;
+    out.write(format!("{}{:16}", created, "").as_bytes())?;
+    out.write(&fit_text(&dataset.label, 40, options.encoding)?)?;
+    out.write(&[b' '; 8])?;
+
+    out.header(
+        if v8 { "NAMSTV8" } else { "NAMESTR" },
+        [0, variables.len(), 0, 0, 0, 0],
+    )?;
+    let mut position = 0;
+    for (number, variable) in variables.iter().enumerate() {
+        out.write(&variable.namestr(number + 1, position, options.version))?;
+        position += variable.length();
+    }
+    out.finish_record()?;
+
+    // Version 8 keeps labels longer than the 40 bytes of the namestr in
+    // records of their own
+    let long_labels: Vec<(usize, &XportVariable)> = variables
+        .iter()
+        .enumerate()
+        .filter(|(_, variable)| variable.label.len() > 40)
+        .collect();
+    if v8 && !long_labels.is_empty() {
+        out.header("LABELV8", [long_labels.len(), 0, 0, 0, 0, 0])?;
+        for (index, variable) in long_labels {
+            out.write(&(index as i16 + 1).to_be_bytes())?;
+            out.write(&(variable.name.len() as i16).to_be_bytes())?;
+            out.write(&(variable.label.len() as i16).to_be_bytes())?;
+            out.write(variable.name.as_bytes())?;
+            out.write(&variable.label)?;
+        }
+        out.finish_record()?;
+    }
+
+    out.header(if v8 { "OBSV8" } else { "OBS" }, [0; 6])?;
+    for row in 0..df.height() {
+        for variable in &variables {
+            variable.write_value(row, &mut out)?;
+        }
+    }
+    out.finish_record()?;
+    out.writer.flush()?;
+    Ok(())
+}
+
+/// Writes the 80-byte records of a transport file.
+struct XportRecords<W: Write> {
+    writer: W,
+    position: usize,
+}
+
+impl<W: Write> XportRecords<W> {
+    fn new(writer: W) -> Self {
+        XportRecords {
+            writer,
+            position: 0,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.position += bytes.len();
+        Ok(())
+    }
+
+    /// Pads the current record with blanks to its full 80 bytes.
+    fn finish_record(&mut self) -> std::io::Result<()> {
+        let rest = (80 - self.position % 80) % 80;
+        self.write(&vec![b' '; rest])
+    }
+
+    fn header(&mut self, name: &str, numbers: [usize; 6]) -> std::io::Result<()> {
+        let [a, b, c, d, e, f] = numbers;
+        let record = format!(
+            "HEADER RECORD*******{:<8}HEADER RECORD!!!!!!!{:05}{:05}{:05}{:05}{:05}{:05}  ",
+            name, a, b, c, d, e, f
+        );
+        self.write(record.as_bytes())
+    }
+}
+
+/// One column of a transport file with its values in SAS form.
+struct XportVariable {
+    name: String,
+    label: Vec<u8>,
+    /// The label cut to the 40 bytes the namestr has room for.
+    short_label: Vec<u8>,
+    format: (&'static str, i16),
+    values: XportValues,
+}
+
+enum XportValues {
+    Numeric(Vec<Option<f64>>),
+    Text {
+        values: Vec<Option<Vec<u8>>>,
+        length: usize,
+    },
+}
+
+impl XportVariable {
+    fn new(
+        column: &Series,
+        name: String,
+        label: &str,
+        options: &XportOptions,
+    ) -> Result<Self, DataGeneratorError> {
+        let numbers = |column: &Series, to_sas: &dyn Fn(f64) -> f64| -> PolarsResult<_> {
+            Ok(XportValues::Numeric(
+                column
+                    .cast(&DataType::Float64)?
+                    .f64()?
+                    .into_iter()
+                    .map(|value| value.map(to_sas))
+                    .collect(),
+            ))
+        };
+        let (format, values) = match column.dtype() {
+            DataType::Date => (
+                ("DATE", 9),
+                numbers(column, &|days| days + SAS_EPOCH_OFFSET_DAYS as f64)?,
+            ),
+            DataType::Time => (
+                ("TIME", 8),
+                numbers(column, &|nanoseconds| nanoseconds / 1e9)?,
+            ),
+            DataType::Datetime(unit, _) => {
+                let per_second = match unit {
+                    TimeUnit::Milliseconds => 1e3,
+                    TimeUnit::Microseconds => 1e6,
+                    TimeUnit::Nanoseconds => 1e9,
+                };
+                let offset = (SAS_EPOCH_OFFSET_DAYS * SECONDS_PER_DAY) as f64;
+                (
+                    ("DATETIME", 20),
+                    numbers(column, &|value| value / per_second + offset)?,
+                )
+            }
+            DataType::String => {
+                let values = column
+                    .str()?
+                    .into_iter()
+                    .map(|value| value.map(|v| encode_text(v, options.encoding)).transpose())
+                    .collect::<Result<Vec<_>, _>>()?;
+                let length = values
+                    .iter()
+                    .flatten()
+                    .map(Vec::len)
+                    .max()
+                    .unwrap_or(0)
+                    .max(1);
+                if length > options.version.max_text_length() {
+                    return Err(DataGeneratorError::Other(format!(
+                        "Column {} has values of {} bytes, more than the {} a SAS {:?} transport file allows",
+                        column.name(),
+                        length,
+                        options.version.max_text_length(),
+                        options.version
+                    )));
+                }
+                (("", 0), XportValues::Text { values, length })
+            }
+            dtype if dtype.is_numeric() || dtype.is_bool() => (("", 0), numbers(column, &|v| v)?),
+            dtype => {
+                return Err(DataGeneratorError::Other(format!(
+                    "Column {} of type {} can't be written to a SAS transport file",
+                    column.name(),
+                    dtype
+                )))
+            }
+        };
+        Ok(XportVariable {
+            name,
+            label: encode_text(label, options.encoding)?,
+            short_label: fit_text(label, 40, options.encoding)?,
+            format,
+            values,
+        })
+    }
+
+    /// The number of bytes a value takes up in an observation.
+    fn length(&self) -> usize {
+        match &self.values {
+            XportValues::Numeric(_) => 8,
+            XportValues::Text { length, .. } => *length,
+        }
+    }
+
+    /// The 140-byte description of the variable.
+    fn namestr(&self, number: usize, position: usize, version: XportVersion) -> Vec<u8> {
+        let short = |value: usize| (value as i16).to_be_bytes();
+        let padded = |bytes: &[u8], width: usize| {
+            let mut field = bytes[..bytes.len().min(width)].to_vec();
+            field.resize(width, b' ');
+            field
+        };
+        let numeric = matches!(self.values, XportValues::Numeric(_));
+        let mut namestr = Vec::with_capacity(140);
+        namestr.extend(short(if numeric { 1 } else { 2 }));
+        namestr.extend(short(0));
+        namestr.extend(short(self.length()));
+        namestr.extend(short(number));
+        namestr.extend(padded(self.name.as_bytes(), 8));
+        namestr.extend(&self.short_label);
+        namestr.extend(padded(self.format.0.as_bytes(), 8));
+        namestr.extend(self.format.1.to_be_bytes());
+        namestr.extend(short(0));
+        namestr.extend(short(0));
+        namestr.extend([0; 2]);
+        namestr.extend(padded(b"", 8));
+        namestr.extend(short(0));
+        namestr.extend(short(0));
+        namestr.extend((position as i32).to_be_bytes());
+        if version == XportVersion::V8 {
+            namestr.extend(padded(self.name.as_bytes(), 32));
+            namestr.extend(short(self.label.len()));
+        }
+        namestr.resize(140, 0);
+        namestr
+    }
+
+    fn write_value(
+        &self,
+        row: usize,
+        out: &mut XportRecords<impl Write>,
+    ) -> Result<(), DataGeneratorError> {
+        match &self.values {
+            XportValues::Numeric(values) => {
+                let bytes = match values[row] {
+                    Some(value) if value.is_finite() => ibm_double(value).ok_or_else(|| {
+                        DataGeneratorError::Other(format!(
+                            "The value {} of column {} is too large for a SAS transport file",
+                            value, self.name
+                        ))
+                    })?,
+                    // The standard SAS missing value
+                    _ => [b'.', 0, 0, 0, 0, 0, 0, 0],
+                };
+                out.write(&bytes)?;
+            }
+            XportValues::Text { values, length } => {
+                let value = values[row].as_deref().unwrap_or_default();
+                out.write(value)?;
+                out.write(&vec![b' '; length - value.len()])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Converts a number to the IBM System/360 double precision format that
+/// transport files use, or `None` if it's too large for it.
+fn ibm_double(value: f64) -> Option<[u8; 8]> {
+    if value == 0.0 {
+        return Some([0; 8]);
+    }
+    let bits = value.abs().to_bits();
+    let biased_exponent = ((bits >> 52) & 0x7ff) as i32;
+    let (exponent, mantissa) = if biased_exponent == 0 {
+        // Subnormal numbers are far too small for IBM floats anyway
+        return Some([0; 8]);
+    } else {
+        (biased_exponent - 1023, (bits & ((1 << 52) - 1)) | (1 << 52))
+    };
+
+    // value = mantissa * 2^(exponent - 52) = fraction * 16^(ibm_exponent - 64)
+    // with a 56-bit fraction between 1/16 and 1
+    let power_of_16 = (exponent + 1 + 3).div_euclid(4);
+    let shift = exponent + 4 - 4 * power_of_16;
+    let fraction = mantissa << shift;
+    let ibm_exponent = power_of_16 + 64;
+    if ibm_exponent > 127 {
+        return None;
+    }
+    if ibm_exponent < 0 {
+        return Some([0; 8]);
+    }
+
+    let mut bytes = fraction.to_be_bytes();
+    bytes[0] = ibm_exponent as u8 | if value < 0.0 { 0x80 } else { 0 };
+    Some(bytes)
+}
+
+/// Makes valid and unique SAS names of column names. Version 5 names are
+/// also upper case.
+fn sas_names(names: &[&str], version: XportVersion) -> Vec<String> {
+    identifiers(
+        names,
+        version.max_name_length(),
+        version == XportVersion::V5,
+    )
+}
+
+/// Makes valid and unique identifiers of column names: letters, digits and
+/// underscores, not starting with a digit and at most `max_length` long.
+/// Names that collide once shortened get a number at the end.
+fn identifiers(names: &[&str], max_length: usize, upper_case: bool) -> Vec<String> {
+    let mut taken = HashSet::new();
+    names
+        .iter()
+        .map(|name| {
+            let mut base: String = name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect();
+            if base.is_empty() || base.starts_with(|c: char| c.is_ascii_digit()) {
+                base.insert(0, '_');
+            }
+            if upper_case {
+                base.make_ascii_uppercase();
+            }
+            let mut candidate = base[..base.len().min(max_length)].to_string();
+            let mut number = 1;
+            while taken.contains(&candidate.to_ascii_uppercase()) {
+                let suffix = number.to_string();
+                let kept = base.len().min(max_length - suffix.len());
+                candidate = format!("{}{}", &base[..kept], suffix);
+                number += 1;
+            }
+            taken.insert(candidate.to_ascii_uppercase());
+            candidate
+        })
+        .collect()
+}
+
+/// Encodes text into a field of `width` bytes, cut at a character boundary
+/// if it's too long and padded with blanks.
+fn fit_text(
+    text: &str,
+    width: usize,
+    encoding: TextEncoding,
+) -> Result<Vec<u8>, DataGeneratorError> {
+    let mut end = text.len();
+    let mut bytes = encode_text(text, encoding)?;
+    while bytes.len() > width {
+        end = text[..end]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(index, _)| index);
+        bytes = encode_text(&text[..end], encoding)?;
+    }
+    bytes.resize(width, b' ');
+    Ok(bytes)
+}
+
+/// The labels of a Stata dataset.
+pub struct StataDataset {
+    pub label: String,
+    /// Variable labels by column name.
+    pub column_labels: HashMap<String, String>,
+    /// Codes with their labels by column name, written as value labels.
+    /// String columns of numeric codes are stored as numbers, and other
+    /// string columns with codes as the numbers 1, 2, ... in code order,
+    /// labelled with the code and its label.
+    pub value_labels: HashMap<String, Vec<(String, String)>>,
+}
+
+/// Stata's format 118 (Stata 14 and later) type codes.
+const STATA_DOUBLE: u16 = 65526;
+const STATA_FLOAT: u16 = 65527;
+const STATA_LONG: u16 = 65528;
+const STATA_INT: u16 = 65529;
+const STATA_BYTE: u16 = 65530;
+const STATA_MAX_STR: usize = 2045;
+
+/// Milliseconds from the Stata epoch, 1 January 1960, to the Unix epoch.
+const STATA_EPOCH_OFFSET_MS: f64 = (SAS_EPOCH_OFFSET_DAYS * SECONDS_PER_DAY * 1000) as f64;
+
+/// Writes a DataFrame as a Stata dataset in format 118, read by Stata 14 and
+/// later.
+///
+/// Integers are stored in the smallest Stata type that holds them, dates as
+/// `%td` days and datetimes as `%tc` milliseconds since 1960, times as `%tc`
+/// milliseconds since midnight, and nulls as Stata's `.`.
+pub fn write_dataframe_to_dta(
+    df: &DataFrame,
+    file_path: &Path,
+    dataset: &StataDataset,
+) -> Result<(), DataGeneratorError> {
+    let column_names: Vec<&str> = df.get_columns().iter().map(|c| c.name().as_str()).collect();
+    let names = identifiers(&column_names, 32, false);
+    let variables = df
+        .get_columns()
+        .iter()
+        .zip(names)
+        .map(|(column, name)| {
+            let codes = dataset.value_labels.get(column.name().as_str());
+            StataVariable::new(column, name, codes)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    // Null-terminated text fields
+    let fixed = |text: &str, width: usize| {
+        let mut field = truncate_text(text, width - 1).as_bytes().to_vec();
+        field.resize(width, 0);
+        field
+    };
+
+    let mut out = BufWriter::new(File::create(file_path)?);
+    let mut map = [0u64; 14];
+    out.write_all(b"<stata_dta><header><release>118</release><byteorder>LSF</byteorder>")?;
+    out.write_all(b"<K>")?;
+    out.write_all(&(variables.len() as u16).to_le_bytes())?;
+    out.write_all(b"</K><N>")?;
+    out.write_all(&(df.height() as u64).to_le_bytes())?;
+    out.write_all(b"</N><label>")?;
+    let label = truncate_text(&dataset.label, 80);
+    out.write_all(&(label.len() as u16).to_le_bytes())?;
+    out.write_all(label.as_bytes())?;
+    out.write_all(b"</label><timestamp>")?;
+    let timestamp = chrono::Local::now().format("%d %b %Y %H:%M").to_string();
+    out.write_all(&[timestamp.len() as u8])?;
+    out.write_all(timestamp.as_bytes())?;
+    out.write_all(b"</timestamp></header>")?;
+
+    // The map of where each section starts is filled in at the end
+    map[1] = out.stream_position()?;
+    out.write_all(b"<map>")?;
+    out.write_all(&[0; 14 * 8])?;
+    out.write_all(b"</map>")?;
+
+    map[2] = out.stream_position()?;
+    out.write_all(b"<variable_types>")?;
+    for variable in &variables {
+        out.write_all(&variable.type_code().to_le_bytes())?;
+    }
+    out.write_all(b"</variable_types>")?;
+
+    map[3] = out.stream_position()?;
+    out.write_all(b"<varnames>")?;
+    for variable in &variables {
+        out.write_all(&fixed(&variable.name, 129))?;
+    }
+    out.write_all(b"</varnames>")?;
+
+    map[4] = out.stream_position()?;
+    out.write_all(b"<sortlist>")?;
+    out.write_all(&vec![0; (variables.len() + 1) * 2])?;
+    out.write_all(b"</sortlist>")?;
+
+    map[5] = out.stream_position()?;
+    out.write_all(b"<formats>")?;
+    for variable in &variables {
+        out.write_all(&fixed(&variable.format(), 57))?;
+    }
+    out.write_all(b"</formats>")?;
+
+    map[6] = out.stream_position()?;
+    out.write_all(b"<value_label_names>")?;
+    for variable in &variables {
+        let label_name = if variable.value_labels.is_empty() {
+            ""
+        } else {
+            variable.name.as_str()
+        };
+        out.write_all(&fixed(label_name, 129))?;
+    }
+    out.write_all(b"</value_label_names>")?;
+
+    map[7] = out.stream_position()?;
+    out.write_all(b"<variable_labels>")?;
+    for column in &column_names {
+        let label = dataset
+            .column_labels
+            .get(*column)
+            .map_or("", String::as_str);
+        out.write_all(&fixed(label, 321))?;
+    }
+    out.write_all(b"</variable_labels>")?;
+
+    map[8] = out.stream_position()?;
+    out.write_all(b"<characteristics></characteristics>")?;
+
+    map[9] = out.stream_position()?;
+    out.write_all(b"<data>")?;
+    for row in 0..df.height() {
+        for variable in &variables {
+            variable.write_value(row, &mut out)?;
+        }
+    }
+    out.write_all(b"</data>")?;
+
+    map[10] = out.stream_position()?;
+    out.write_all(b"<strls></strls>")?;
+
+    map[11] = out.stream_position()?;
+    out.write_all(b"<value_labels>")?;
+    for variable in variables.iter().filter(|v| !v.value_labels.is_empty()) {
+        let mut offsets = Vec::new();
+        let mut text = Vec::new();
+        for (_, label) in &variable.value_labels {
+            offsets.push(text.len() as u32);
+            text.extend(label.as_bytes());
+            text.push(0);
+        }
+        let table_length = 8 + 8 * variable.value_labels.len() + text.len();
+        out.write_all(b"<lbl>")?;
+        out.write_all(&(table_length as u32).to_le_bytes())?;
+        out.write_all(&fixed(&variable.name, 129))?;
+        out.write_all(&[0; 3])?;
+        out.write_all(&(variable.value_labels.len() as u32).to_le_bytes())?;
+        out.write_all(&(text.len() as u32).to_le_bytes())?;
+        for offset in offsets {
+            out.write_all(&offset.to_le_bytes())?;
+        }
+        for (value, _) in &variable.value_labels {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        out.write_all(&text)?;
+        out.write_all(b"</lbl>")?;
+    }
+    out.write_all(b"</value_labels>")?;
+
+    map[12] = out.stream_position()?;
+    out.write_all(b"</stata_dta>")?;
+    map[13] = out.stream_position()?;
+
+    out.seek(SeekFrom::Start(map[1] + b"<map>".len() as u64))?;
+    for offset in map {
+        out.write_all(&offset.to_le_bytes())?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// One column of a Stata dataset with its values in Stata form.
+struct StataVariable {
+    name: String,
+    values: StataValues,
+    /// Stata's display format for dates and times, if the column has one.
+    date_format: Option<&'static str>,
+    value_labels: Vec<(i32, String)>,
+}
+
+enum StataValues {
+    Byte(Vec<i8>),
+    Int(Vec<i16>),
+    Long(Vec<i32>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    Str { values: Vec<Vec<u8>>, width: usize },
+}
+
+impl StataVariable {
+    fn new(
+        column: &Series,
+        name: String,
+        codes: Option<&Vec<(String, String)>>,
+    ) -> Result<Self, DataGeneratorError> {
+        let mut value_labels = Vec::new();
+        let mut date_format = None;
+        let values = match column.dtype() {
+            DataType::String if codes.is_some() => {
+                let strings = column.str()?;
+                let codes = codes.into_iter().flatten();
+                // Numeric codes such as "5100" keep their value; Stata only
+                // labels numbers, so other codes become their position
+                let integer =
+                    |value: &str| value.parse::<i32>().ok().filter(|n| n.to_string() == value);
+                let numbers: Option<Vec<Option<i64>>> = strings
+                    .into_iter()
+                    .map(|value| match value {
+                        Some(value) => integer(value).map(|n| Some(n as i64)),
+                        None => Some(None),
+                    })
+                    .collect();
+                match numbers {
+                    Some(numbers) => {
+                        value_labels = codes
+                            .filter_map(|(code, label)| Some((integer(code)?, label.clone())))
+                            .collect();
+                        stata_integers(&numbers)
+                    }
+                    None => {
+                        let mut positions: HashMap<String, i64> = HashMap::new();
+                        for (code, label) in codes {
+                            let position = positions.len() as i64 + 1;
+                            positions.insert(code.clone(), position);
+                            value_labels.push((position as i32, format!("{} {}", code, label)));
+                        }
+                        let mut encoded = Vec::with_capacity(column.len());
+                        for value in strings {
+                            encoded.push(value.map(|value| {
+                                // Codes missing from the mapping are labelled with themselves
+                                let next = positions.len() as i64 + 1;
+                                *positions.entry(value.to_string()).or_insert_with(|| {
+                                    value_labels.push((next as i32, value.to_string()));
+                                    next
+                                })
+                            }));
+                        }
+                        stata_integers(&encoded)
+                    }
+                }
+            }
+            DataType::String => {
+                let values: Vec<Vec<u8>> = column
+                    .str()?
+                    .into_iter()
+                    .map(|value| value.unwrap_or_default().as_bytes().to_vec())
+                    .collect();
+                let width = values.iter().map(Vec::len).max().unwrap_or(0).max(1);
+                if width > STATA_MAX_STR {
+                    return Err(DataGeneratorError::Other(format!(
+                        "Column {} has values of {} bytes, more than the {} a Stata str allows",
+                        column.name(),
+                        width,
+                        STATA_MAX_STR
+                    )));
+                }
+                StataValues::Str { values, width }
+            }
+            DataType::Date => {
+                date_format = Some("%td");
+                let days = column.cast(&DataType::Int32)?;
+                let days: Vec<Option<i64>> = days
+                    .i32()?
+                    .into_iter()
+                    .map(|days| days.map(|days| days as i64 + SAS_EPOCH_OFFSET_DAYS))
+                    .collect();
+                stata_integers(&days)
+            }
+            DataType::Datetime(unit, _) => {
+                date_format = Some("%tc");
+                let per_ms = match unit {
+                    TimeUnit::Milliseconds => 1.0,
+                    TimeUnit::Microseconds => 1e3,
+                    TimeUnit::Nanoseconds => 1e6,
+                };
+                stata_doubles(column, |value| value / per_ms + STATA_EPOCH_OFFSET_MS)?
+            }
+            DataType::Time => {
+                date_format = Some("%tcHH:MM:SS");
+                stata_doubles(column, |nanoseconds| nanoseconds / 1e6)?
+            }
+            DataType::Float32 => StataValues::Float(
+                column
+                    .f32()?
+                    .into_iter()
+                    .map(|value| {
+                        value
+                            .filter(|v| v.is_finite())
+                            .unwrap_or(f32::from_bits(0x7f00_0000))
+                    })
+                    .collect(),
+            ),
+            DataType::Float64 => stata_doubles(column, |value| value)?,
+            dtype if dtype.is_integer() || dtype.is_bool() => {
+                let integers = column.cast(&DataType::Int64)?;
+                let integers: Vec<Option<i64>> = integers.i64()?.into_iter().collect();
+                if let Some(codes) = codes {
+                    value_labels = codes
+                        .iter()
+                        .filter_map(|(code, label)| Some((code.parse().ok()?, label.clone())))
+                        .collect();
+                }
+                stata_integers(&integers)
+            }
+            dtype => {
+                return Err(DataGeneratorError::Other(format!(
+                    "Column {} of type {} can't be written to a Stata dataset",
+                    column.name(),
+                    dtype
+                )))
+            }
+        };
+        Ok(StataVariable {
+            name,
+            values,
+            date_format,
+            value_labels,
+        })
+    }
+
+    fn type_code(&self) -> u16 {
+        match &self.values {
+            StataValues::Byte(_) => STATA_BYTE,
+            StataValues::Int(_) => STATA_INT,
+            StataValues::Long(_) => STATA_LONG,
+            StataValues::Float(_) => STATA_FLOAT,
+            StataValues::Double(_) => STATA_DOUBLE,
+            StataValues::Str { width, .. } => *width as u16,
+        }
+    }
+
+    /// The display format Stata gives new variables of the type.
+    fn format(&self) -> String {
+        if let Some(format) = self.date_format {
+            return format.to_string();
+        }
+        match &self.values {
+            StataValues::Byte(_) | StataValues::Int(_) => "%8.0g".to_string(),
+            StataValues::Long(_) => "%12.0g".to_string(),
+            StataValues::Float(_) => "%9.0g".to_string(),
+            StataValues::Double(_) => "%10.0g".to_string(),
+            StataValues::Str { width, .. } => format!("%{}s", width),
+        }
+    }
+
+    fn write_value(&self, row: usize, out: &mut impl Write) -> std::io::Result<()> {
+        match &self.values {
+            StataValues::Byte(values) => out.write_all(&values[row].to_le_bytes()),
+            StataValues::Int(values) => out.write_all(&values[row].to_le_bytes()),
+            StataValues::Long(values) => out.write_all(&values[row].to_le_bytes()),
+            StataValues::Float(values) => out.write_all(&values[row].to_le_bytes()),
+            StataValues::Double(values) => out.write_all(&values[row].to_le_bytes()),
+            StataValues::Str { values, width } => {
+                out.write_all(&values[row])?;
+                out.write_all(&vec![0; width - values[row].len()])
+            }
+        }
+    }
+}
+
+/// Cuts UTF-8 text to at most `max_bytes` bytes at a character boundary.
+fn truncate_text(text: &str, max_bytes: usize) -> &str {
+    let mut end = text.len().min(max_bytes);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Stores integers in the smallest Stata type whose range, which stops short
+/// of the type's missing values, holds them all.
+fn stata_integers(values: &[Option<i64>]) -> StataValues {
+    let min = values.iter().flatten().min().copied().unwrap_or(0);
+    let max = values.iter().flatten().max().copied().unwrap_or(0);
+    if min >= -127 && max <= 100 {
+        StataValues::Byte(values.iter().map(|v| v.map_or(101, |v| v as i8)).collect())
+    } else if min >= -32767 && max <= 32740 {
+        StataValues::Int(
+            values
+                .iter()
+                .map(|v| v.map_or(32741, |v| v as i16))
+                .collect(),
+        )
+    } else if min >= -2_147_483_647 && max <= 2_147_483_620 {
+        StataValues::Long(
+            values
+                .iter()
+                .map(|v| v.map_or(2_147_483_621, |v| v as i32))
+                .collect(),
+        )
+    } else {
+        StataValues::Double(
+            values
+                .iter()
+                .map(|v| v.map_or(STATA_MISSING_DOUBLE, |v| v as f64))
+                .collect(),
+        )
+    }
+}
+
+/// Stata's `.` for doubles, 2^1023.
+const STATA_MISSING_DOUBLE: f64 = 8.98846567431158e307;
+
+fn stata_doubles(column: &Series, to_stata: impl Fn(f64) -> f64) -> PolarsResult<StataValues> {
+    Ok(StataValues::Double(
+        column
+            .cast(&DataType::Float64)?
+            .f64()?
+            .into_iter()
+            .map(|value| match value {
+                Some(value) if value.is_finite() => to_stata(value),
+                _ => STATA_MISSING_DOUBLE,
+            })
+            .collect(),
+    ))
+}
+
+/// How SQLite output lays out the years of a register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SqliteTables {
+    /// One table per register, with the year of each row in a YEAR column
+    Register,
+    /// One table per register and year, e.g. akm_2020
+    Year,
+}
+
+/// The column holding the year of each row when a register has one table.
+pub const SQLITE_YEAR_COLUMN: &str = "YEAR";
+
+/// Columns registers are joined on, which are indexed wherever they appear.
+/// LPR3 calls the PNR CPR.
+const SQLITE_INDEXED_COLUMNS: [&str; 4] = ["PNR", "CPR", "RECNUM", "DW_EK_KONTAKT"];
+
+/// A table of an SQLite database with its columns in order.
+pub struct SqliteTable {
+    pub name: String,
+    pub columns: Vec<(String, DataType)>,
+}
+
+impl SqliteTable {
+    /// A table with the given columns, keeping the first of columns listed
+    /// more than once, as schemas do for columns with several periods. Fails
+    /// if they don't all have the same type.
+    pub fn new(
+        name: String,
+        columns: impl IntoIterator<Item = (String, DataType)>,
+    ) -> Result<Self, DataGeneratorError> {
+        let mut unique: Vec<(String, DataType)> = Vec::new();
+        for (column, dtype) in columns {
+            match unique.iter().find(|(existing, _)| *existing == column) {
+                None => unique.push((column, dtype)),
+                Some((_, first)) if *first == dtype => {}
+                Some((_, first)) => {
+                    return Err(DataGeneratorError::Other(format!(
+                        "Column {} of the SQLite table {} is both {} and {}",
+                        column, name, first, dtype
+                    )))
+                }
+            }
+        }
+        Ok(SqliteTable {
+            name,
+            columns: unique,
+        })
+    }
+}
+
+/// The type an SQLite column holding values of a Polars type is declared
+/// with. Dates, times and datetimes are stored as ISO 8601 text, which
+/// SQLite's date and time functions read.
+fn sqlite_type(dtype: &DataType) -> &'static str {
+    match dtype {
+        DataType::Date => "DATE",
+        DataType::Datetime(..) => "DATETIME",
+        DataType::Time => "TIME",
+        DataType::String => "TEXT",
+        dtype if dtype.is_float() => "REAL",
+        _ => "INTEGER",
+    }
+}
+
+/// Quotes a table, column or index name for SQL.
+fn sqlite_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Starts a new SQLite database at `file_path`. An existing database is
+/// deleted with `overwrite` and otherwise left alone with an error, as
+/// writing to it would mix old and new rows.
+pub fn create_sqlite_database(file_path: &Path, overwrite: bool) -> Result<(), DataGeneratorError> {
+    if !file_path.exists() {
+        return Ok(());
+    }
+    if !overwrite {
+        return Err(DataGeneratorError::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!(
+                "The SQLite database '{}' already exists; pass --overwrite to replace it",
+                file_path.display()
+            ),
+        )));
+    }
+    fs::remove_file(file_path)?;
+    Ok(())
+}
+
+/// Appends the rows of a DataFrame to a table of an SQLite database, creating
+/// the database and the table with indexes on the PNR, RECNUM and
+/// DW_EK_KONTAKT columns if they don't exist.
+///
+/// Every column of the DataFrame must be a column of the table; table columns
+/// the DataFrame doesn't have are left null.
+pub fn write_dataframe_to_sqlite(
+    df: &DataFrame,
+    file_path: &Path,
+    table: &SqliteTable,
+) -> Result<(), DataGeneratorError> {
+    let mut connection = Connection::open(file_path)?;
+    let table_name = sqlite_identifier(&table.name);
+    let columns = table
+        .columns
+        .iter()
+        .map(|(name, dtype)| format!("{} {}", sqlite_identifier(name), sqlite_type(dtype)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let exists = connection
+        .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")?
+        .exists([&table.name])?;
+    if !exists {
+        connection.execute(&format!("CREATE TABLE {} ({})", table_name, columns), [])?;
+    }
+
+    let values = df
+        .get_columns()
+        .iter()
+        .map(|column| {
+            if !table
+                .columns
+                .iter()
+                .any(|(name, _)| name == column.name().as_str())
+            {
+                return Err(DataGeneratorError::Other(format!(
+                    "Column {} isn't a column of the SQLite table {}",
+                    column.name(),
+                    table.name
+                )));
+            }
+            sqlite_values(column)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let column_names = df
+        .get_columns()
+        .iter()
+        .map(|column| sqlite_identifier(column.name()))
+        .collect::<Vec<_>>();
+    let placeholders = vec!["?"; column_names.len()].join(", ");
+
+    // One transaction for all rows, as SQLite otherwise syncs after each
+    let transaction = connection.transaction()?;
+    {
+        let mut insert = transaction.prepare(&format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table_name,
+            column_names.join(", "),
+            placeholders
+        ))?;
+        for row in 0..df.height() {
+            insert.execute(params_from_iter(values.iter().map(|column| &column[row])))?;
+        }
+    }
+    transaction.commit()?;
+
+    // Indexing after inserting is faster than keeping the index up to date
+    for (column, _) in &table.columns {
+        if SQLITE_INDEXED_COLUMNS.contains(&column.as_str()) {
+            connection.execute(
+                &format!(
+                    "CREATE INDEX IF NOT EXISTS {} ON {} ({})",
+                    sqlite_identifier(&format!("{}_{}", table.name, column)),
+                    table_name,
+                    sqlite_identifier(column)
+                ),
+                [],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// The values of a column as SQLite values, with nulls as NULL.
+fn sqlite_values(column: &Series) -> Result<Vec<Value>, DataGeneratorError> {
+    let values = match column.dtype() {
+        DataType::String | DataType::Date | DataType::Datetime(..) | DataType::Time => column
+            .cast(&DataType::String)?
+            .str()?
+            .into_iter()
+            .map(|value| value.map_or(Value::Null, |v| Value::Text(v.to_string())))
+            .collect(),
+        dtype if dtype.is_float() => column
+            .cast(&DataType::Float64)?
+            .f64()?
+            .into_iter()
+            .map(|value| value.map_or(Value::Null, Value::Real))
+            .collect(),
+        dtype if dtype.is_integer() || dtype.is_bool() => column
+            .cast(&DataType::Int64)?
+            .i64()?
+            .into_iter()
+            .map(|value| value.map_or(Value::Null, Value::Integer))
+            .collect(),
+        dtype => {
+            return Err(DataGeneratorError::Other(format!(
+                "Column {} of type {} can't be written to SQLite",
+                column.name(),
+                dtype
+            )))
+        }
+    };
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use std::path::PathBuf;
+
+    /// A path in the temporary directory that no other test uses.
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("cdef-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn persons() -> DataFrame {
+        let dates = [NaiveDate::from_ymd_opt(2020, 1, 31), None];
+        DataFrame::new(vec![
+            Series::new("PNR".into(), ["010190-1234", "020290-2345"]),
+            Series::new("ALDER".into(), [30i8, 29]),
+            Series::new("LOEN".into(), [Some(1234.5), None]),
+            Series::new("FOED_DAG".into(), dates),
+        ])
+        .unwrap()
+    }
+
+    fn sqlite_table(name: &str, df: &DataFrame) -> SqliteTable {
+        SqliteTable::new(
+            name.to_string(),
+            df.get_columns()
+                .iter()
+                .map(|column| (column.name().to_string(), column.dtype().clone())),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn sqlite_table_keeps_first_of_repeated_columns() {
+        let table = SqliteTable::new(
+            "akm".to_string(),
+            [
+                ("PNR".to_string(), DataType::String),
+                ("SOCIO".to_string(), DataType::Int16),
+                ("SOCIO".to_string(), DataType::Int16),
+            ],
+        )
+        .unwrap();
+        let names: Vec<&str> = table
+            .columns
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, ["PNR", "SOCIO"]);
+
+        let conflicting = SqliteTable::new(
+            "akm".to_string(),
+            [
+                ("SOCIO".to_string(), DataType::Int16),
+                ("SOCIO".to_string(), DataType::String),
+            ],
+        );
+        assert!(conflicting.is_err());
+    }
+
+    #[test]
+    fn sqlite_writes_typed_columns_and_indexes_join_keys() {
+        let path = temp_path("typed.sqlite");
+        let df = persons();
+        write_dataframe_to_sqlite(&df, &path, &sqlite_table("bef", &df)).unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let sql: String = connection
+            .query_row(
+                "SELECT sql FROM sqlite_master WHERE name = 'bef'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            sql,
+            r#"CREATE TABLE "bef" ("PNR" TEXT, "ALDER" INTEGER, "LOEN" REAL, "FOED_DAG" DATE)"#
+        );
+        let index: String = connection
+            .query_row(
+                "SELECT name FROM sqlite_master WHERE type = 'index'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(index, "bef_PNR");
+
+        let row: (String, i64, Option<f64>, Option<String>) = connection
+            .query_row(
+                "SELECT PNR, ALDER, LOEN, FOED_DAG FROM bef ORDER BY PNR LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            row,
+            (
+                "010190-1234".to_string(),
+                30,
+                Some(1234.5),
+                Some("2020-01-31".to_string())
+            )
+        );
+        let nulls: i64 = connection
+            .query_row(
+                "SELECT count(*) FROM bef WHERE LOEN IS NULL AND FOED_DAG IS NULL",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(nulls, 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sqlite_appends_years_to_one_table() {
+        let path = temp_path("years.sqlite");
+        let mut columns = vec![(SQLITE_YEAR_COLUMN.to_string(), DataType::Int32)];
+        columns.extend(sqlite_table("bef", &persons()).columns);
+        let table = SqliteTable::new("bef".to_string(), columns).unwrap();
+        for year in [2019, 2020] {
+            let mut df = persons();
+            df.with_column(Series::new(SQLITE_YEAR_COLUMN.into(), [year, year]))
+                .unwrap();
+            write_dataframe_to_sqlite(&df, &path, &table).unwrap();
+        }
+
+        let connection = Connection::open(&path).unwrap();
+        let years: Vec<(i64, i64)> = connection
+            .prepare("SELECT YEAR, count(*) FROM bef GROUP BY YEAR")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(years, [(2019, 2), (2020, 2)]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sqlite_database_is_only_replaced_with_overwrite() {
+        let path = temp_path("existing.sqlite");
+        let df = persons();
+        write_dataframe_to_sqlite(&df, &path, &sqlite_table("bef", &df)).unwrap();
+
+        assert!(create_sqlite_database(&path, false).is_err());
+        assert!(path.exists());
+        create_sqlite_database(&path, true).unwrap();
+        assert!(!path.exists());
+        create_sqlite_database(&path, false).unwrap();
+    }
+
+    #[test]
+    fn sqlite_rejects_columns_outside_the_table() {
+        let path = temp_path("outside.sqlite");
+        let table =
+            SqliteTable::new("bef".to_string(), [("PNR".to_string(), DataType::String)]).unwrap();
+        assert!(write_dataframe_to_sqlite(&persons(), &path, &table).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    fn csv_options() -> CsvOptions {
+        CsvOptions {
+            delimiter: b';',
+            decimal_comma: false,
+            date_format: "%d%m%Y".to_string(),
+            encoding: TextEncoding::Utf8,
+            compression: CsvCompression::None,
+        }
+    }
+
+    #[test]
+    fn csv_writes_decimal_commas_in_float_columns_only() {
+        let path = temp_path("decimal.csv");
+        let options = CsvOptions {
+            decimal_comma: true,
+            ..csv_options()
+        };
+        write_dataframe_to_single_csv(&persons(), &path, &options).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "PNR;ALDER;LOEN;FOED_DAG\n010190-1234;30;1234,5;31012020\n020290-2345;29;;\n"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn csv_encodes_latin1() {
+        let path = temp_path("latin1.csv");
+        let df = DataFrame::new(vec![Series::new("NAVN".into(), ["Søren Ærø"])]).unwrap();
+        let options = CsvOptions {
+            encoding: TextEncoding::Latin1,
+            ..csv_options()
+        };
+        write_dataframe_to_single_csv(&df, &path, &options).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"NAVN\nS\xf8ren \xc6r\xf8\n");
+        fs::remove_file(&path).unwrap();
+
+        let df = DataFrame::new(vec![Series::new("NAVN".into(), ["5 €"])]).unwrap();
+        assert!(write_dataframe_to_single_csv(&df, &path, &options).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn csv_extension_follows_delimiter_and_compression() {
+        let options = |delimiter, compression| CsvOptions {
+            delimiter,
+            compression,
+            ..csv_options()
+        };
+        assert_eq!(options(b';', CsvCompression::None).extension(), "csv");
+        assert_eq!(options(b'\t', CsvCompression::Gzip).extension(), "tsv.gz");
+        assert_eq!(options(b',', CsvCompression::Zstd).extension(), "csv.zst");
+    }
+
+    #[test]
+    fn csv_gzip_round_trips() {
+        use std::io::Read;
+
+        let path = temp_path("persons.csv.gz");
+        let options = CsvOptions {
+            compression: CsvCompression::Gzip,
+            ..csv_options()
+        };
+        write_dataframe_to_single_csv(&persons(), &path, &options).unwrap();
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(File::open(&path).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        assert!(text.starts_with("PNR;ALDER;LOEN;FOED_DAG\n010190-1234;30;1234.5;31012020\n"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ibm_double_matches_known_encodings() {
+        assert_eq!(ibm_double(0.0), Some([0; 8]));
+        assert_eq!(ibm_double(1.0), Some([0x41, 0x10, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(
+            ibm_double(-118.625),
+            Some([0xc2, 0x76, 0xa0, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(
+            ibm_double(0.1),
+            Some([0x40, 0x19, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a])
+        );
+        assert_eq!(ibm_double(1e80), None);
+    }
+
+    #[test]
+    fn sas_names_are_valid_and_unique() {
+        let names = [
+            "FOED_DAG",
+            "DW_EK_KONTAKT",
+            "DW_EK_KONTAKTTYPE",
+            "1SOCIO",
+            "c-tjek",
+        ];
+        assert_eq!(
+            sas_names(&names, XportVersion::V5),
+            ["FOED_DAG", "DW_EK_KO", "DW_EK_K1", "_1SOCIO", "C_TJEK"]
+        );
+        assert_eq!(
+            sas_names(&names, XportVersion::V8),
+            [
+                "FOED_DAG",
+                "DW_EK_KONTAKT",
+                "DW_EK_KONTAKTTYPE",
+                "_1SOCIO",
+                "c_tjek"
+            ]
+        );
+    }
+
+    #[test]
+    fn xport_writes_80_byte_records() {
+        let path = temp_path("persons.xpt");
+        let dataset = XportDataset {
+            name: "bef_2020".to_string(),
+            label: "Befolkningen".to_string(),
+            column_labels: HashMap::from([("LOEN".to_string(), "Løn".to_string())]),
+        };
+        let options = XportOptions {
+            version: XportVersion::V5,
+            encoding: TextEncoding::Latin1,
+        };
+        write_dataframe_to_xport(&persons(), &path, &dataset, &options).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(bytes.len() % 80, 0);
+        let records: Vec<&[u8]> = bytes.chunks(80).collect();
+        assert!(records[0].starts_with(b"HEADER RECORD*******LIBRARY "));
+        assert!(records[3].starts_with(b"HEADER RECORD*******MEMBER  "));
+        assert_eq!(&records[5][8..16], b"BEF_2020");
+        assert!(records[7].starts_with(
+            b"HEADER RECORD*******NAMESTR HEADER RECORD!!!!!!!000000000400000000000000000000"
+        ));
+
+        // Four 140-byte namestrs padded to whole records, then the observations
+        let namestrs = &bytes[8 * 80..8 * 80 + 4 * 140];
+        let loen = &namestrs[2 * 140..3 * 140];
+        assert_eq!(&loen[8..16], b"LOEN    ");
+        assert!(loen[16..56].starts_with(b"L\xf8n "));
+        let obs = 8 * 80 + (4 * 140usize).div_ceil(80) * 80;
+        assert!(bytes[obs..].starts_with(b"HEADER RECORD*******OBS     "));
+
+        // PNR is 11 bytes and the numbers 8 each, with missing values as "."
+        let rows = &bytes[obs + 80..];
+        assert!(rows.starts_with(b"010190-1234"));
+        assert_eq!(&rows[11..19], &ibm_double(30.0).unwrap());
+        assert_eq!(&rows[19..27], &ibm_double(1234.5).unwrap());
+        let foed_dag = NaiveDate::from_ymd_opt(2020, 1, 31).unwrap()
+            - NaiveDate::from_ymd_opt(1960, 1, 1).unwrap();
+        assert_eq!(
+            &rows[27..35],
+            &ibm_double(foed_dag.num_days() as f64).unwrap()
+        );
+        assert_eq!(&rows[35 + 19..35 + 35], b".\0\0\0\0\0\0\0.\0\0\0\0\0\0\0");
+    }
+
+    #[test]
+    fn xport_v5_rejects_long_text() {
+        let path = temp_path("long.xpt");
+        let df = DataFrame::new(vec![Series::new("TEKST".into(), ["x".repeat(201)])]).unwrap();
+        let dataset = XportDataset {
+            name: "tekst".to_string(),
+            label: String::new(),
+            column_labels: HashMap::new(),
+        };
+        let options = XportOptions {
+            version: XportVersion::V5,
+            encoding: TextEncoding::Utf8,
+        };
+        assert!(write_dataframe_to_xport(&df, &path, &dataset, &options).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn stata_integers_use_the_smallest_type() {
+        let type_code = |values: &[Option<i64>]| {
+            StataVariable {
+                name: String::new(),
+                values: stata_integers(values),
+                date_format: None,
+                value_labels: Vec::new(),
+            }
+            .type_code()
+        };
+        assert_eq!(type_code(&[Some(-127), Some(100), None]), STATA_BYTE);
+        assert_eq!(type_code(&[Some(101)]), STATA_INT);
+        assert_eq!(type_code(&[Some(-32767), Some(32740)]), STATA_INT);
+        assert_eq!(type_code(&[Some(32741)]), STATA_LONG);
+        assert_eq!(type_code(&[Some(2_147_483_621)]), STATA_DOUBLE);
+        assert_eq!(type_code(&[None]), STATA_BYTE);
+    }
+
+    /// The offsets of the sections of a format 118 file, read from its map.
+    fn stata_map(bytes: &[u8]) -> Vec<usize> {
+        let start = bytes.windows(5).position(|w| w == b"<map>").unwrap() + 5;
+        bytes[start..start + 14 * 8]
+            .chunks(8)
+            .map(|offset| u64::from_le_bytes(offset.try_into().unwrap()) as usize)
+            .collect()
+    }
+
+    #[test]
+    fn stata_writes_format_118() {
+        let path = temp_path("persons.dta");
+        let dataset = StataDataset {
+            label: "Befolkningen".to_string(),
+            column_labels: HashMap::from([("LOEN".to_string(), "Løn".to_string())]),
+            value_labels: HashMap::new(),
+        };
+        write_dataframe_to_dta(&persons(), &path, &dataset).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(bytes.starts_with(
+            b"<stata_dta><header><release>118</release><byteorder>LSF</byteorder><K>\x04\x00</K><N>\x02\0\0\0\0\0\0\0</N><label>\x0c\x00Befolkningen</label>"
+        ));
+        assert!(bytes.ends_with(b"</stata_dta>"));
+        let map = stata_map(&bytes);
+        assert_eq!(map[13], bytes.len());
+        assert!(bytes[map[9]..].starts_with(b"<data>"));
+
+        let types: Vec<u16> = bytes[map[2] + b"<variable_types>".len()..][..8]
+            .chunks(2)
+            .map(|code| u16::from_le_bytes(code.try_into().unwrap()))
+            .collect();
+        assert_eq!(types, [11, STATA_BYTE, STATA_DOUBLE, STATA_INT]);
+
+        let formats = &bytes[map[5] + b"<formats>".len()..];
+        assert!(formats[3 * 57..].starts_with(b"%td\0"));
+        let labels = &bytes[map[7] + b"<variable_labels>".len()..];
+        assert!(labels[2 * 321..].starts_with("Løn\0".as_bytes()));
+
+        // 11 + 1 + 8 + 2 bytes a row, with nulls as Stata's missing values
+        let rows = &bytes[map[9] + b"<data>".len()..];
+        assert!(rows.starts_with(b"010190-1234\x1e"));
+        assert_eq!(&rows[12..20], &1234.5f64.to_le_bytes());
+        let foed_dag = NaiveDate::from_ymd_opt(2020, 1, 31).unwrap()
+            - NaiveDate::from_ymd_opt(1960, 1, 1).unwrap();
+        assert_eq!(&rows[20..22], &(foed_dag.num_days() as i16).to_le_bytes());
+        assert_eq!(&rows[22 + 12..22 + 20], &STATA_MISSING_DOUBLE.to_le_bytes());
+        assert_eq!(&rows[22 + 20..22 + 22], &32741i16.to_le_bytes());
+    }
+
+    #[test]
+    fn stata_labels_string_codes() {
+        let path = temp_path("codes.dta");
+        let df = DataFrame::new(vec![
+            Series::new("STATSB".into(), [Some("5100"), Some("5180"), None]),
+            Series::new("C_ADIAG".into(), [Some("DI21"), Some("DX99"), Some("DI21")]),
+        ])
+        .unwrap();
+        let codes = |codes: &[(&str, &str)]| {
+            codes
+                .iter()
+                .map(|(code, label)| (code.to_string(), label.to_string()))
+                .collect()
+        };
+        let dataset = StataDataset {
+            label: String::new(),
+            column_labels: HashMap::new(),
+            value_labels: HashMap::from([
+                (
+                    "STATSB".to_string(),
+                    codes(&[("5100", "Danmark"), ("5180", "Polen")]),
+                ),
+                (
+                    "C_ADIAG".to_string(),
+                    codes(&[("DI10", "Hypertension"), ("DI21", "AMI")]),
+                ),
+            ]),
+        };
+        write_dataframe_to_dta(&df, &path, &dataset).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let map = stata_map(&bytes);
+
+        // Numeric codes keep their value, other codes are numbered in code
+        // order, with codes outside the mapping after those in it
+        let rows = &bytes[map[9] + b"<data>".len()..];
+        let row = |i: usize| {
+            let row = &rows[i * 3..i * 3 + 3];
+            (i16::from_le_bytes([row[0], row[1]]), row[2] as i8)
+        };
+        assert_eq!(row(0), (5100, 2));
+        assert_eq!(row(1), (5180, 3));
+        assert_eq!(row(2), (32741, 2));
+
+        let value_labels = &bytes[map[11]..map[12]];
+        let tables: Vec<&[u8]> = value_labels
+            .windows(5)
+            .enumerate()
+            .filter(|(_, w)| *w == b"<lbl>")
+            .map(|(start, _)| {
+                let table = &value_labels[start + 5..];
+                let length = u32::from_le_bytes(table[..4].try_into().unwrap()) as usize;
+                &table[4..4 + 129 + 3 + length]
+            })
+            .collect();
+        assert_eq!(tables.len(), 2);
+        assert!(tables[0].starts_with(b"STATSB\0"));
+        assert!(tables[0].ends_with(b"Danmark\0Polen\0"));
+        assert!(tables[1].starts_with(b"C_ADIAG\0"));
+        assert!(tables[1].ends_with(b"DI10 Hypertension\0DI21 AMI\0DX99\0"));
+    }
+
+    #[test]
+    fn ipc_round_trips_with_every_compression() {
+        for compression in [
+            IpcOutputCompression::None,
+            IpcOutputCompression::Lz4,
+            IpcOutputCompression::Zstd,
+        ] {
+            let path = temp_path(&format!("persons-{:?}.arrow", compression));
+            write_dataframe_to_single_ipc(&mut persons(), &path, compression).unwrap();
+            let read = crate::read::read_single_ipc_file(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert!(read.equals_missing(&persons()), "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn reading_tells_ipc_from_parquet_by_content() {
+        // Each file has the other format's extension
+        let ipc = temp_path("ipc.parquet");
+        let parquet = temp_path("parquet.arrow");
+        write_dataframe_to_single_ipc(&mut persons(), &ipc, IpcOutputCompression::None).unwrap();
+        write_dataframe_to_single_parquet(&mut persons(), &parquet).unwrap();
+        for path in [&ipc, &parquet] {
+            let read = crate::read::read_single_file(path).unwrap();
+            fs::remove_file(path).unwrap();
+            assert!(read.equals_missing(&persons()), "{}", path.display());
+        }
+    }
+}